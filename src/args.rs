@@ -1,22 +1,159 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
+use yaul::instructions::Instruction;
+use yaul::{Pass, PcRange, Watchpoint};
 
 #[derive(Parser)]
 #[command(name = "yaul")]
 #[command(author = "Jørgen Hanssen")]
 #[command(version = "0.1.0")]
 #[command(about = "Yet Another Useless Language")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub run: Option<RunArgs>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Runs a program, the same as `yaul <file>`.
+    Run(RunArgs),
+    /// Steps through a program interactively.
+    Debug(DebugArgs),
+    /// Compiles a program to bytecode that `yaul run` loads without parsing.
+    Compile(CompileArgs),
+    /// Prints a program or bytecode file in canonical YAUL syntax.
+    Disasm(DisasmArgs),
+    /// Writes the control-flow graph of a program in Graphviz DOT format.
+    Cfg(CfgArgs),
+    /// Warns about registers read before they are written, values that are
+    /// never read and unreachable code.
+    Check(CheckArgs),
+}
+
+#[derive(clap::Args)]
+pub struct RunArgs {
     #[arg(long, default_value_t = 8192, value_parser = clap::value_parser!(u64).range(1..))]
     pub registers: u64,
 
-    /// Runs instructions faster by sacrificing safety.
+    /// Skips bounds checks on register access everywhere. Without it they are
+    /// only skipped where the program provably stays within the registers.
     #[arg(long)]
     pub fast: bool,
 
+    /// Optimizes the program first.
+    #[arg(short = 'O', long)]
+    pub optimize: bool,
+
+    /// Leaves out these optimization passes: jump-threading, dead-stores or constant-folding.
+    #[arg(long, requires = "optimize", value_delimiter = ',')]
+    pub skip_pass: Vec<Pass>,
+
+    /// Writes a line-delimited JSON trace of the executed instructions to this file.
+    #[arg(long)]
+    pub logs: Option<PathBuf>,
+
+    /// Only traces instructions in these index ranges, e.g. `3`, `10..20` or `5..=7`.
+    #[arg(long, requires = "logs", value_delimiter = ',')]
+    pub logs_pc: Vec<PcRange>,
+
+    /// Only traces these opcodes, e.g. `add,jmp`.
+    #[arg(long, requires = "logs", value_delimiter = ',', value_parser = opcode)]
+    pub logs_op: Vec<String>,
+
+    /// Prints how often each function, label and instruction was executed to stderr.
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Writes the profile as folded stacks for flamegraph tools to this file.
+    #[arg(long)]
+    pub profile_folded: Option<PathBuf>,
+
+    /// Prints which lines never ran and which conditional jumps only went one way to stderr.
+    #[arg(long)]
+    pub coverage: bool,
+
+    /// Writes line and branch coverage as an lcov tracefile to this file. Not
+    /// available for bytecode, which has no source lines.
+    #[arg(long)]
+    pub lcov: Option<PathBuf>,
+
+    /// Writes every value read by `in`, `cin`, `time` and `sys` to this file.
+    #[arg(long, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Feeds `in`, `cin`, `time` and `sys` the values from a file written by --record.
     #[arg(long)]
-    pub logs: Option<String>,
+    pub replay: Option<PathBuf>,
+
+    /// Reports every read or write of these registers, e.g. `5`, `10..20:rw` or `3..=4:r`.
+    #[arg(long, value_delimiter = ',')]
+    pub watch: Vec<Watchpoint>,
 
-    #[arg(required = true)]
     pub file: PathBuf,
 }
+
+#[derive(clap::Args)]
+pub struct DebugArgs {
+    #[arg(long, default_value_t = 8192, value_parser = clap::value_parser!(u64).range(1..))]
+    pub registers: u64,
+
+    /// Stops before the instruction at these labels or lines, e.g. `loop,12`.
+    #[arg(long = "break", value_delimiter = ',')]
+    pub breakpoints: Vec<String>,
+
+    /// Stops after these registers are accessed, e.g. `5`, `10..20:rw` or `3..=4:r`.
+    #[arg(long, value_delimiter = ',')]
+    pub watch: Vec<Watchpoint>,
+
+    pub file: PathBuf,
+}
+
+#[derive(clap::Args)]
+pub struct CompileArgs {
+    /// Where to write the bytecode, defaults to the file with a `.yaulc` extension.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    pub file: PathBuf,
+}
+
+#[derive(clap::Args)]
+pub struct DisasmArgs {
+    /// Optimizes the program first.
+    #[arg(short = 'O', long)]
+    pub optimize: bool,
+
+    /// Leaves out these optimization passes: jump-threading, dead-stores or constant-folding.
+    #[arg(long, requires = "optimize", value_delimiter = ',')]
+    pub skip_pass: Vec<Pass>,
+
+    pub file: PathBuf,
+}
+
+#[derive(clap::Args)]
+pub struct CfgArgs {
+    /// Where to write the graph, defaults to stdout.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    pub file: PathBuf,
+}
+
+#[derive(clap::Args)]
+pub struct CheckArgs {
+    pub file: PathBuf,
+}
+
+/// Parses an instruction mnemonic in any case into upper case.
+fn opcode(s: &str) -> Result<String, String> {
+    let opcode = s.to_uppercase();
+    if Instruction::OPCODES.contains(&opcode.as_str()) {
+        Ok(opcode)
+    } else {
+        Err(format!("Unknown opcode: {}", s))
+    }
+}
@@ -0,0 +1,549 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::cfg::local_successors;
+use crate::instructions::{Destination, Instruction, Label, Source};
+
+/// How many times an instruction is revisited before its intervals are widened.
+const WIDEN_AFTER: usize = 3;
+
+/// The values a register can hold, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Interval {
+    lo: i64,
+    hi: i64,
+}
+
+const TOP: Interval = Interval {
+    lo: i64::MIN,
+    hi: i64::MAX,
+};
+
+impl Interval {
+    fn constant(value: i64) -> Interval {
+        Interval {
+            lo: value,
+            hi: value,
+        }
+    }
+
+    /// `None` if the interval is empty.
+    fn new(lo: i64, hi: i64) -> Option<Interval> {
+        (lo <= hi).then_some(Interval { lo, hi })
+    }
+
+    fn hull(self, other: Interval) -> Interval {
+        Interval {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+
+    fn contains(self, other: Interval) -> bool {
+        self.lo <= other.lo && other.hi <= self.hi
+    }
+
+    /// Applies `op` to every combination of ends. Only sound for operations
+    /// that are monotonic in each operand, and gives up if any of them overflows,
+    /// as the runner wraps around.
+    fn corners(self, other: Interval, op: fn(i64, i64) -> Option<i64>) -> Interval {
+        let corners = [
+            op(self.lo, other.lo),
+            op(self.lo, other.hi),
+            op(self.hi, other.lo),
+            op(self.hi, other.hi),
+        ];
+        match corners {
+            [Some(a), Some(b), Some(c), Some(d)] => Interval {
+                lo: a.min(b).min(c).min(d),
+                hi: a.max(b).max(c).max(d),
+            },
+            _ => TOP,
+        }
+    }
+
+    fn add(self, other: Interval) -> Interval {
+        self.corners(other, i64::checked_add)
+    }
+
+    fn sub(self, other: Interval) -> Interval {
+        self.corners(other, i64::checked_sub)
+    }
+
+    fn mul(self, other: Interval) -> Interval {
+        self.corners(other, i64::checked_mul)
+    }
+
+    fn div(self, divisor: Interval) -> Interval {
+        // Truncating division is monotonic as long as the divisor keeps its sign.
+        if divisor.lo > 0 || divisor.hi < 0 {
+            self.corners(divisor, i64::checked_div)
+        } else {
+            TOP
+        }
+    }
+
+    fn rem(self, divisor: Interval) -> Interval {
+        // The result is closer to 0 than both operands and has the sign of the dividend.
+        let (Some(a), Some(b)) = (divisor.lo.checked_abs(), divisor.hi.checked_abs()) else {
+            return TOP;
+        };
+        let max = a.max(b) - 1;
+        if max < 0 {
+            // Always divides by 0.
+            return TOP;
+        }
+        Interval {
+            lo: if self.lo >= 0 { 0 } else { self.lo.max(-max) },
+            hi: if self.hi <= 0 { 0 } else { self.hi.min(max) },
+        }
+    }
+}
+
+/// What is known about the registers before an instruction runs. Registers
+/// that aren't in the map can hold anything.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct State {
+    registers: BTreeMap<usize, Interval>,
+}
+
+impl State {
+    fn get(&self, register: usize) -> Interval {
+        self.registers.get(&register).copied().unwrap_or(TOP)
+    }
+
+    fn set(&mut self, register: usize, value: Interval) {
+        if value == TOP {
+            self.registers.remove(&register);
+        } else {
+            self.registers.insert(register, value);
+        }
+    }
+
+    fn read(&self, source: &Source) -> Interval {
+        match source {
+            Source::Data(value) => Interval::constant(*value),
+            Source::Address(register) => self.get(*register),
+            Source::Reference(_) => TOP,
+        }
+    }
+
+    fn write(&mut self, destination: &Destination, value: Interval) {
+        match destination {
+            Destination::Address(register) => self.set(*register, value),
+            Destination::Reference(pointer) => {
+                let pointer = self.get(*pointer);
+                self.write_any(pointer, value);
+            }
+        }
+    }
+
+    /// Writes `value` to one of the registers in `range`, or none of them.
+    fn write_any(&mut self, range: Interval, value: Interval) {
+        let (Ok(lo), Ok(hi)) = (usize::try_from(range.lo.max(0)), usize::try_from(range.hi)) else {
+            return;
+        };
+        let affected = self
+            .registers
+            .range(lo..=hi)
+            .map(|(register, _)| *register)
+            .collect::<Vec<usize>>();
+        for register in affected {
+            let joined = self.get(register).hull(value);
+            self.set(register, joined);
+        }
+    }
+
+    /// Keeps only what is known in both states.
+    fn join(&self, other: &State) -> State {
+        let registers = self
+            .registers
+            .iter()
+            .filter_map(|(register, a)| {
+                let b = other.registers.get(register)?;
+                Some((*register, a.hull(*b)))
+            })
+            .filter(|(_, value)| *value != TOP)
+            .collect();
+        State { registers }
+    }
+
+    /// Like `join`, but moves growing ends out to the next threshold so loops settle.
+    fn widen(&self, next: &State, thresholds: &BTreeSet<i64>) -> State {
+        let mut widened = self.join(next);
+        for (register, value) in widened.registers.iter_mut() {
+            let old = self.registers[register];
+            if value.lo < old.lo {
+                value.lo = thresholds
+                    .range(..=value.lo)
+                    .next_back()
+                    .copied()
+                    .unwrap_or(i64::MIN);
+            }
+            if value.hi > old.hi {
+                value.hi = thresholds
+                    .range(value.hi..)
+                    .next()
+                    .copied()
+                    .unwrap_or(i64::MAX);
+            }
+        }
+        widened.registers.retain(|_, value| *value != TOP);
+        widened
+    }
+
+    /// Whether everything this state allows is allowed by `other`.
+    fn within(&self, other: &State) -> bool {
+        other
+            .registers
+            .iter()
+            .all(|(register, value)| value.contains(self.get(*register)))
+    }
+
+    /// Narrows the state to where `a <op> b` holds, `None` if it never does.
+    fn assume(&self, a: &Source, b: &Source, condition: Condition) -> Option<State> {
+        let (x, y) = (self.read(a), self.read(b));
+        let (x, y) = match condition {
+            Condition::Less => (
+                Interval::new(x.lo, x.hi.min(y.hi.checked_sub(1)?))?,
+                Interval::new(y.lo.max(x.lo.checked_add(1)?), y.hi)?,
+            ),
+            Condition::GreaterOrEqual => (
+                Interval::new(x.lo.max(y.lo), x.hi)?,
+                Interval::new(y.lo, y.hi.min(x.hi))?,
+            ),
+            Condition::Equal => {
+                let both = Interval::new(x.lo.max(y.lo), x.hi.min(y.hi))?;
+                (both, both)
+            }
+            Condition::NotEqual => match (x.lo == x.hi, y.lo == y.hi) {
+                (true, true) if x == y => return None,
+                (_, true) => (trim(x, y.lo)?, y),
+                (true, _) => (x, trim(y, x.lo)?),
+                _ => (x, y),
+            },
+        };
+
+        let mut state = self.clone();
+        if let Source::Address(register) = a {
+            state.set(*register, x);
+        }
+        if let Source::Address(register) = b {
+            // `a` and `b` may be the same register, keep what was learned through `a`.
+            let y = Interval::new(
+                y.lo.max(state.get(*register).lo),
+                y.hi.min(state.get(*register).hi),
+            )?;
+            state.set(*register, y);
+        }
+        Some(state)
+    }
+}
+
+/// Removes `value` from `interval` if it is one of its ends.
+fn trim(interval: Interval, value: i64) -> Option<Interval> {
+    if interval.lo == value {
+        Interval::new(value.checked_add(1)?, interval.hi)
+    } else if interval.hi == value {
+        Interval::new(interval.lo, value.checked_sub(1)?)
+    } else {
+        Some(interval)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Condition {
+    Less,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        match self {
+            Condition::Less => Condition::GreaterOrEqual,
+            Condition::GreaterOrEqual => Condition::Less,
+            Condition::Equal => Condition::NotEqual,
+            Condition::NotEqual => Condition::Equal,
+        }
+    }
+}
+
+/// The highest register each instruction can access, directly or through a
+/// reference, or `None` if that can't be proven. Unreachable instructions are
+/// `None` too.
+///
+/// This holds as long as execution started at the first instruction with an
+/// empty call stack, whatever the registers held then, and nothing but the
+/// program changed them since. Host functions may change any register.
+pub(crate) fn register_bounds(instructions: &[Instruction]) -> Vec<Option<usize>> {
+    let states = analyze(instructions);
+    instructions
+        .iter()
+        .zip(&states)
+        .map(|(instruction, state)| bound(instruction, state.as_ref()?))
+        .collect()
+}
+
+fn target(label: &Label) -> Option<usize> {
+    match label {
+        Label::Instruction(target) => Some(*target),
+        Label::Label(_) => None,
+    }
+}
+
+/// Constants in the program and their neighbours, which loop bounds tend to be.
+fn thresholds(instructions: &[Instruction]) -> BTreeSet<i64> {
+    let mut thresholds = BTreeSet::from([0, 1]);
+    let mut add = |source: &Source| {
+        if let Source::Data(value) = source {
+            thresholds.extend([value.saturating_sub(1), *value, value.saturating_add(1)]);
+        }
+    };
+    for instruction in instructions {
+        match instruction {
+            Instruction::Set(a, _) | Instruction::CharInput(_, a) => add(a),
+            Instruction::Add(a, b, _)
+            | Instruction::Subtract(a, b, _)
+            | Instruction::Multiply(a, b, _)
+            | Instruction::Divide(a, b, _)
+            | Instruction::Modulo(a, b, _)
+            | Instruction::JumpGreaterThan(a, b, _)
+            | Instruction::JumpEqual(a, b, _)
+            | Instruction::JumpLessThan(a, b, _) => {
+                add(a);
+                add(b);
+            }
+            _ => {}
+        }
+    }
+    thresholds
+}
+
+/// Where each `ret` can go back to: after any call to a function it is part of.
+/// A function is everything reachable from where it starts without returning.
+fn return_sites(instructions: &[Instruction]) -> HashMap<usize, Vec<usize>> {
+    let mut calls: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (pc, instruction) in instructions.iter().enumerate() {
+        if let Instruction::Call(Label::Instruction(callee)) = instruction {
+            calls.entry(*callee).or_default().push(pc + 1);
+        }
+    }
+
+    let mut return_sites: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (callee, sites) in calls {
+        let mut visited = BTreeSet::new();
+        let mut work = vec![callee];
+        while let Some(pc) = work.pop() {
+            if pc >= instructions.len() || !visited.insert(pc) {
+                continue;
+            }
+            if let Instruction::Return = instructions[pc] {
+                return_sites.entry(pc).or_default().extend(&sites);
+            }
+            work.extend(local_successors(instructions, pc));
+        }
+    }
+    return_sites
+}
+
+/// The state before every instruction, `None` if it can't be reached.
+fn analyze(instructions: &[Instruction]) -> Vec<Option<State>> {
+    let len = instructions.len();
+    let thresholds = thresholds(instructions);
+    let return_sites = return_sites(instructions);
+
+    let mut states: Vec<Option<State>> = vec![None; len];
+    let mut visits = vec![0; len];
+    let mut work = BTreeSet::new();
+    if len > 0 {
+        states[0] = Some(State::default());
+        work.insert(0);
+    }
+
+    // Lowest pc first, so loops settle before what comes after them.
+    while let Some(pc) = work.pop_first() {
+        let Some(state) = states[pc].clone() else {
+            continue;
+        };
+
+        for (successor, incoming) in transfer(&instructions[pc], pc, state, &return_sites) {
+            if successor >= len {
+                continue;
+            }
+            let merged = match &states[successor] {
+                None => incoming,
+                Some(existing) if incoming.within(existing) => continue,
+                Some(existing) => {
+                    visits[successor] += 1;
+                    if visits[successor] > WIDEN_AFTER {
+                        existing.widen(&incoming, &thresholds)
+                    } else {
+                        existing.join(&incoming)
+                    }
+                }
+            };
+            states[successor] = Some(merged);
+            work.insert(successor);
+        }
+    }
+    states
+}
+
+/// Runs an instruction on `state`, giving the state at each place execution can go next.
+fn transfer(
+    instruction: &Instruction,
+    pc: usize,
+    mut state: State,
+    return_sites: &HashMap<usize, Vec<usize>>,
+) -> Vec<(usize, State)> {
+    let next = pc + 1;
+    match instruction {
+        // With an empty call stack `ret` ends the program.
+        Instruction::Return => {
+            return return_sites
+                .get(&pc)
+                .into_iter()
+                .flatten()
+                .map(|site| (*site, state.clone()))
+                .collect()
+        }
+        Instruction::Fault(_) => return Vec::new(),
+        Instruction::Jump(label) => return target(label).map(|t| (t, state)).into_iter().collect(),
+        Instruction::Call(label) => return target(label).map(|t| (t, state)).into_iter().collect(),
+        // `a > b` is `b < a`.
+        Instruction::JumpGreaterThan(a, b, label) => {
+            return branch(&state, b, a, Condition::Less, label, next)
+        }
+        Instruction::JumpLessThan(a, b, label) => {
+            return branch(&state, a, b, Condition::Less, label, next)
+        }
+        Instruction::JumpEqual(a, b, label) => {
+            return branch(&state, a, b, Condition::Equal, label, next)
+        }
+        Instruction::Set(a, d) => {
+            let value = state.read(a);
+            state.write(d, value);
+        }
+        Instruction::Add(a, b, d) => {
+            let value = state.read(a).add(state.read(b));
+            state.write(d, value);
+        }
+        Instruction::Subtract(a, b, d) => {
+            let value = state.read(a).sub(state.read(b));
+            state.write(d, value);
+        }
+        Instruction::Multiply(a, b, d) => {
+            let value = state.read(a).mul(state.read(b));
+            state.write(d, value);
+        }
+        Instruction::Divide(a, b, d) => {
+            let value = state.read(a).div(state.read(b));
+            state.write(d, value);
+        }
+        Instruction::Modulo(a, b, d) => {
+            let value = state.read(a).rem(state.read(b));
+            state.write(d, value);
+        }
+        Instruction::Input(d) | Instruction::Time(d) | Instruction::Syscall(d, ..) => {
+            state.write(d, TOP)
+        }
+        Instruction::CharInput(d, size) => {
+            // Writes some of the registers from the destination on.
+            let start = match d {
+                Destination::Address(register) => Interval::constant(*register as i64),
+                Destination::Reference(pointer) => state.get(*pointer),
+            };
+            let end = start.add(state.read(size)).hi;
+            if let Some(written) = Interval::new(start.lo, end) {
+                state.write_any(written, TOP);
+            }
+        }
+        Instruction::HostCall(_) => state = State::default(),
+        Instruction::Output(_) | Instruction::CharOutput(_) => {}
+    }
+    vec![(next, state)]
+}
+
+/// The states after a conditional jump that jumps when `a <condition> b`.
+fn branch(
+    state: &State,
+    a: &Source,
+    b: &Source,
+    condition: Condition,
+    label: &Label,
+    next: usize,
+) -> Vec<(usize, State)> {
+    let mut successors = Vec::with_capacity(2);
+    if let (Some(target), Some(jumped)) = (target(label), state.assume(a, b, condition)) {
+        successors.push((target, jumped));
+    }
+    if let Some(fell_through) = state.assume(a, b, condition.negate()) {
+        successors.push((next, fell_through));
+    }
+    successors
+}
+
+/// The highest register `instruction` can access when run in `state`.
+fn bound(instruction: &Instruction, state: &State) -> Option<usize> {
+    let source = |source: &Source| -> Option<usize> {
+        match source {
+            Source::Data(_) => Some(0),
+            Source::Address(register) => Some(*register),
+            Source::Reference(pointer) => Some((*pointer).max(pointed(state.get(*pointer))?)),
+        }
+    };
+    let destination = |destination: &Destination| -> Option<usize> {
+        match destination {
+            Destination::Address(register) => Some(*register),
+            Destination::Reference(pointer) => Some((*pointer).max(pointed(state.get(*pointer))?)),
+        }
+    };
+
+    match instruction {
+        Instruction::Return
+        | Instruction::Jump(_)
+        | Instruction::Call(_)
+        | Instruction::Fault(_)
+        | Instruction::HostCall(_) => Some(0),
+        Instruction::Set(a, d) => Some(source(a)?.max(destination(d)?)),
+        Instruction::Input(d) | Instruction::Time(d) => destination(d),
+        Instruction::CharInput(d, size) => {
+            let start = match d {
+                Destination::Address(register) => {
+                    Interval::constant(i64::try_from(*register).ok()?)
+                }
+                Destination::Reference(pointer) => state.get(*pointer),
+            };
+            // At most one register per byte read.
+            let last = start.add(state.read(size)).hi.checked_sub(1)?;
+            let last = pointed(Interval::new(start.lo, last.max(start.hi))?)?;
+            Some(source(size)?.max(destination(d)?).max(last))
+        }
+        Instruction::Output(a) | Instruction::CharOutput(a) => source(a),
+        Instruction::Add(a, b, d)
+        | Instruction::Subtract(a, b, d)
+        | Instruction::Multiply(a, b, d)
+        | Instruction::Divide(a, b, d)
+        | Instruction::Modulo(a, b, d) => Some(source(a)?.max(source(b)?).max(destination(d)?)),
+        Instruction::JumpGreaterThan(a, b, _)
+        | Instruction::JumpEqual(a, b, _)
+        | Instruction::JumpLessThan(a, b, _) => Some(source(a)?.max(source(b)?)),
+        Instruction::Syscall(d, number, a1, a2, a3, a4, a5, a6) => {
+            let mut bound = destination(d)?.max(source(number)?);
+            for arg in [a1, a2, a3, a4, a5, a6].into_iter().flatten() {
+                bound = bound.max(source(arg)?);
+            }
+            Some(bound)
+        }
+    }
+}
+
+/// The highest register a reference holding a value in `pointer` can point at,
+/// `None` if it might be negative.
+fn pointed(pointer: Interval) -> Option<usize> {
+    if pointer.lo < 0 {
+        return None;
+    }
+    usize::try_from(pointer.hi).ok()
+}
@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::instructions::{Destination, Instruction, Label, Source};
+use crate::program::Program;
+use crate::source::SourceMap;
+
+/// The first bytes of every `.yaulc` file.
+pub const MAGIC: &[u8; 5] = b"YAULC";
+
+/// Bumped whenever the encoding changes. Older versions are rejected rather than guessed at.
+pub const VERSION: u16 = 1;
+
+/// Size of the magic, version and checksum in front of the body.
+const HEADER_LEN: usize = MAGIC.len() + 2 + 4;
+
+/// Marks a missing optional `SYS` argument.
+const NONE: u8 = 0xff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BytecodeError {
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    ChecksumMismatch {
+        expected: u32,
+        found: u32,
+    },
+    Truncated,
+    TrailingBytes,
+    InvalidOpcode(u8),
+    InvalidOperand(u8),
+    InvalidConstant(u32),
+    InvalidUtf8,
+    /// A label or jump points past the end of the program.
+    InvalidTarget(usize),
+    /// A count, index or jump target doesn't fit in a `u32`.
+    TooLarge(usize),
+    /// Bytecode can only be produced from programs whose labels are all resolved.
+    UnresolvedLabel(String),
+}
+
+/// Returns whether `bytes` start with the header of a `.yaulc` file of the
+/// current version. Text that merely starts with `MAGIC` is not bytecode.
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.len() >= HEADER_LEN
+        && bytes.starts_with(MAGIC)
+        && bytes[MAGIC.len()..MAGIC.len() + 2] == VERSION.to_le_bytes()
+}
+
+/// Encodes the instructions and labels of `program`. Source locations are not kept.
+///
+/// The file starts with `MAGIC`, a little-endian `u16` version and the CRC-32 of
+/// everything after it. The body is a constant pool holding every string, the
+/// labels as pairs of constant index and target, and the instructions, each an
+/// opcode byte followed by its operands. Counts, indices and jump targets are
+/// `u32`, registers `u64` and values `i64`, all little-endian.
+pub fn encode(program: &Program) -> Result<Vec<u8>, BytecodeError> {
+    let mut encoder = Encoder::default();
+
+    let mut labels = program.labels().iter().collect::<Vec<_>>();
+    labels.sort();
+    let labels = labels
+        .into_iter()
+        .map(|(name, pc)| Ok((encoder.constant(name)?, to_u32(*pc)?)))
+        .collect::<Result<Vec<_>, BytecodeError>>()?;
+
+    let mut instructions = Vec::new();
+    for instruction in program.instructions() {
+        encoder.instruction(instruction, &mut instructions)?;
+    }
+
+    let mut body = Vec::new();
+    push_u32(&mut body, to_u32(encoder.constants.len())?);
+    for constant in &encoder.constants {
+        push_u32(&mut body, to_u32(constant.len())?);
+        body.extend_from_slice(constant.as_bytes());
+    }
+    push_u32(&mut body, to_u32(labels.len())?);
+    for (name, pc) in labels {
+        push_u32(&mut body, name);
+        push_u32(&mut body, pc);
+    }
+    push_u32(&mut body, to_u32(program.instructions().len())?);
+    body.extend_from_slice(&instructions);
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    push_u32(&mut bytes, crc32(&body));
+    bytes.extend_from_slice(&body);
+    Ok(bytes)
+}
+
+/// Decodes a program produced by `encode`. `file` is only used to name the program.
+pub fn decode(bytes: &[u8], file: PathBuf) -> Result<Program, BytecodeError> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(BytecodeError::InvalidMagic);
+    }
+    let mut header = Decoder {
+        bytes: &bytes[MAGIC.len()..],
+        constants: Vec::new(),
+    };
+    let version = u16::from_le_bytes(header.take::<2>()?);
+    if version != VERSION {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+    let expected = header.u32()?;
+    let found = crc32(header.bytes);
+    if expected != found {
+        return Err(BytecodeError::ChecksumMismatch { expected, found });
+    }
+
+    let mut decoder = Decoder {
+        bytes: header.bytes,
+        constants: Vec::new(),
+    };
+    for _ in 0..decoder.u32()? {
+        let len = decoder.u32()? as usize;
+        let constant = std::str::from_utf8(decoder.slice(len)?)
+            .map_err(|_| BytecodeError::InvalidUtf8)?
+            .to_string();
+        decoder.constants.push(constant);
+    }
+
+    let mut labels = HashMap::new();
+    for _ in 0..decoder.u32()? {
+        let name = decoder.constant()?;
+        labels.insert(name, decoder.u32()? as usize);
+    }
+
+    // Counts come from the file, so grow the vector as instructions are read
+    // instead of trusting the count with an allocation up front.
+    let mut instructions = Vec::new();
+    for _ in 0..decoder.u32()? {
+        instructions.push(decoder.instruction()?);
+    }
+
+    if !decoder.bytes.is_empty() {
+        return Err(BytecodeError::TrailingBytes);
+    }
+
+    let targets = instructions
+        .iter()
+        .filter_map(|instruction| match instruction.target() {
+            Some(Label::Instruction(target)) => Some(target),
+            _ => None,
+        });
+    if let Some(target) = labels
+        .values()
+        .chain(targets)
+        .find(|target| **target > instructions.len())
+    {
+        return Err(BytecodeError::InvalidTarget(*target));
+    }
+
+    Ok(Program::new(instructions, labels, SourceMap::new(file)))
+}
+
+#[derive(Default)]
+struct Encoder {
+    constants: Vec<String>,
+    indices: HashMap<String, u32>,
+}
+
+impl Encoder {
+    fn constant(&mut self, text: &str) -> Result<u32, BytecodeError> {
+        if let Some(index) = self.indices.get(text) {
+            return Ok(*index);
+        }
+        let index = to_u32(self.constants.len())?;
+        self.constants.push(text.to_string());
+        self.indices.insert(text.to_string(), index);
+        Ok(index)
+    }
+
+    fn instruction(
+        &mut self,
+        instruction: &Instruction,
+        out: &mut Vec<u8>,
+    ) -> Result<(), BytecodeError> {
+        match instruction {
+            Instruction::Return => out.push(0),
+            Instruction::Set(value, destination) => {
+                out.push(1);
+                push_source(out, value);
+                push_destination(out, destination);
+            }
+            Instruction::Input(destination) => {
+                out.push(2);
+                push_destination(out, destination);
+            }
+            Instruction::CharInput(destination, size) => {
+                out.push(3);
+                push_destination(out, destination);
+                push_source(out, size);
+            }
+            Instruction::Output(value) => {
+                out.push(4);
+                push_source(out, value);
+            }
+            Instruction::CharOutput(value) => {
+                out.push(5);
+                push_source(out, value);
+            }
+            Instruction::Add(a, b, destination)
+            | Instruction::Subtract(a, b, destination)
+            | Instruction::Multiply(a, b, destination)
+            | Instruction::Divide(a, b, destination)
+            | Instruction::Modulo(a, b, destination) => {
+                out.push(match instruction {
+                    Instruction::Add(..) => 6,
+                    Instruction::Subtract(..) => 7,
+                    Instruction::Multiply(..) => 8,
+                    Instruction::Divide(..) => 9,
+                    _ => 10,
+                });
+                push_source(out, a);
+                push_source(out, b);
+                push_destination(out, destination);
+            }
+            Instruction::Jump(label) => {
+                out.push(11);
+                push_label(out, label)?;
+            }
+            Instruction::JumpGreaterThan(a, b, label)
+            | Instruction::JumpEqual(a, b, label)
+            | Instruction::JumpLessThan(a, b, label) => {
+                out.push(match instruction {
+                    Instruction::JumpGreaterThan(..) => 12,
+                    Instruction::JumpEqual(..) => 13,
+                    _ => 14,
+                });
+                push_source(out, a);
+                push_source(out, b);
+                push_label(out, label)?;
+            }
+            Instruction::Call(label) => {
+                out.push(15);
+                push_label(out, label)?;
+            }
+            Instruction::HostCall(name) => {
+                out.push(16);
+                push_u32(out, self.constant(name)?);
+            }
+            Instruction::Time(destination) => {
+                out.push(17);
+                push_destination(out, destination);
+            }
+            Instruction::Fault(message) => {
+                out.push(18);
+                push_u32(out, self.constant(message)?);
+            }
+            Instruction::Syscall(destination, sysno, a1, a2, a3, a4, a5, a6) => {
+                out.push(19);
+                push_destination(out, destination);
+                push_source(out, sysno);
+                for arg in [a1, a2, a3, a4, a5, a6] {
+                    match arg {
+                        Some(arg) => push_source(out, arg),
+                        None => out.push(NONE),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn to_u32(value: usize) -> Result<u32, BytecodeError> {
+    u32::try_from(value).map_err(|_| BytecodeError::TooLarge(value))
+}
+
+fn push_source(out: &mut Vec<u8>, source: &Source) {
+    match source {
+        Source::Data(value) => {
+            out.push(0);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        Source::Address(i) => {
+            out.push(1);
+            out.extend_from_slice(&(*i as u64).to_le_bytes());
+        }
+        Source::Reference(i) => {
+            out.push(2);
+            out.extend_from_slice(&(*i as u64).to_le_bytes());
+        }
+    }
+}
+
+fn push_destination(out: &mut Vec<u8>, destination: &Destination) {
+    let (tag, i) = match destination {
+        Destination::Address(i) => (1, i),
+        Destination::Reference(i) => (2, i),
+    };
+    out.push(tag);
+    out.extend_from_slice(&(*i as u64).to_le_bytes());
+}
+
+fn push_label(out: &mut Vec<u8>, label: &Label) -> Result<(), BytecodeError> {
+    match label {
+        Label::Instruction(pc) => {
+            push_u32(out, to_u32(*pc)?);
+            Ok(())
+        }
+        Label::Label(name) => Err(BytecodeError::UnresolvedLabel(name.clone())),
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    constants: Vec<String>,
+}
+
+impl Decoder<'_> {
+    fn slice(&mut self, len: usize) -> Result<&[u8], BytecodeError> {
+        if self.bytes.len() < len {
+            return Err(BytecodeError::Truncated);
+        }
+        let (slice, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(slice)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], BytecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.slice(N)?);
+        Ok(array)
+    }
+
+    fn byte(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, BytecodeError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn constant(&mut self) -> Result<String, BytecodeError> {
+        let index = self.u32()?;
+        self.constants
+            .get(index as usize)
+            .cloned()
+            .ok_or(BytecodeError::InvalidConstant(index))
+    }
+
+    fn source(&mut self) -> Result<Source, BytecodeError> {
+        let tag = self.byte()?;
+        self.tagged_source(tag)
+    }
+
+    fn tagged_source(&mut self, tag: u8) -> Result<Source, BytecodeError> {
+        match tag {
+            0 => Ok(Source::Data(self.u64()? as i64)),
+            1 => Ok(Source::Address(self.u64()? as usize)),
+            2 => Ok(Source::Reference(self.u64()? as usize)),
+            _ => Err(BytecodeError::InvalidOperand(tag)),
+        }
+    }
+
+    fn optional_source(&mut self) -> Result<Option<Source>, BytecodeError> {
+        match self.byte()? {
+            NONE => Ok(None),
+            tag => self.tagged_source(tag).map(Some),
+        }
+    }
+
+    fn destination(&mut self) -> Result<Destination, BytecodeError> {
+        match self.byte()? {
+            1 => Ok(Destination::Address(self.u64()? as usize)),
+            2 => Ok(Destination::Reference(self.u64()? as usize)),
+            tag => Err(BytecodeError::InvalidOperand(tag)),
+        }
+    }
+
+    fn label(&mut self) -> Result<Label, BytecodeError> {
+        Ok(Label::Instruction(self.u32()? as usize))
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, BytecodeError> {
+        let instruction = match self.byte()? {
+            0 => Instruction::Return,
+            1 => Instruction::Set(self.source()?, self.destination()?),
+            2 => Instruction::Input(self.destination()?),
+            3 => Instruction::CharInput(self.destination()?, self.source()?),
+            4 => Instruction::Output(self.source()?),
+            5 => Instruction::CharOutput(self.source()?),
+            6 => Instruction::Add(self.source()?, self.source()?, self.destination()?),
+            7 => Instruction::Subtract(self.source()?, self.source()?, self.destination()?),
+            8 => Instruction::Multiply(self.source()?, self.source()?, self.destination()?),
+            9 => Instruction::Divide(self.source()?, self.source()?, self.destination()?),
+            10 => Instruction::Modulo(self.source()?, self.source()?, self.destination()?),
+            11 => Instruction::Jump(self.label()?),
+            12 => Instruction::JumpGreaterThan(self.source()?, self.source()?, self.label()?),
+            13 => Instruction::JumpEqual(self.source()?, self.source()?, self.label()?),
+            14 => Instruction::JumpLessThan(self.source()?, self.source()?, self.label()?),
+            15 => Instruction::Call(self.label()?),
+            16 => Instruction::HostCall(self.constant()?),
+            17 => Instruction::Time(self.destination()?),
+            18 => Instruction::Fault(self.constant()?),
+            19 => Instruction::Syscall(
+                self.destination()?,
+                self.source()?,
+                self.optional_source()?,
+                self.optional_source()?,
+                self.optional_source()?,
+                self.optional_source()?,
+                self.optional_source()?,
+                self.optional_source()?,
+            ),
+            opcode => return Err(BytecodeError::InvalidOpcode(opcode)),
+        };
+        Ok(instruction)
+    }
+}
+
+/// CRC-32 as used by zip and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BytecodeError::InvalidMagic => write!(f, "Not a YAUL bytecode file"),
+            BytecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported bytecode version {}, expected {}",
+                version, VERSION
+            ),
+            BytecodeError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "Checksum mismatch, expected {:08x} but found {:08x}",
+                expected, found
+            ),
+            BytecodeError::Truncated => write!(f, "Bytecode is truncated"),
+            BytecodeError::TrailingBytes => {
+                write!(f, "Unexpected bytes after the last instruction")
+            }
+            BytecodeError::InvalidOpcode(opcode) => write!(f, "Invalid opcode: {}", opcode),
+            BytecodeError::InvalidOperand(tag) => write!(f, "Invalid operand tag: {}", tag),
+            BytecodeError::InvalidConstant(index) => {
+                write!(f, "Constant index out of bounds: {}", index)
+            }
+            BytecodeError::InvalidUtf8 => write!(f, "Constant is not valid UTF-8"),
+            BytecodeError::InvalidTarget(target) => {
+                write!(f, "Jump target out of range: {}", target)
+            }
+            BytecodeError::TooLarge(value) => write!(f, "Too large for bytecode: {}", value),
+            BytecodeError::UnresolvedLabel(label) => write!(f, "Unresolved label: {}", label),
+        }
+    }
+}
+
+impl Error for BytecodeError {}
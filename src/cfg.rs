@@ -0,0 +1,356 @@
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::disasm;
+use crate::instructions::{Instruction, Label};
+use crate::program::Program;
+
+/// The control-flow graph of a program, with its instructions split into basic
+/// blocks. Block 0 is the entry block.
+///
+/// A `call` has edges to the called block and to the instruction after it, as
+/// if the call returned straight away. `ret`, `fault` and running off the end
+/// of the program end a block without successors.
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    blocks: Vec<BasicBlock>,
+    /// Index of the block each instruction belongs to.
+    block_of: Vec<usize>,
+    reachable: Vec<bool>,
+    /// Immediate dominator of each block, `None` for the entry and unreachable blocks.
+    idom: Vec<Option<usize>>,
+}
+
+/// Instructions that always run one after the other, entered only at `start`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// One past the last instruction of the block.
+    pub end: usize,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>,
+}
+
+/// A natural loop: the blocks that can reach one of the `latches` without
+/// going through `header`, which dominates all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: usize,
+    /// Blocks with an edge back to the header.
+    pub latches: Vec<usize>,
+    /// Every block in the loop including the header, in order.
+    pub blocks: Vec<usize>,
+}
+
+impl BasicBlock {
+    pub fn instructions(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+impl Cfg {
+    /// Builds the graph of `program`, also starting a block at every label so
+    /// the blocks line up with the source.
+    pub fn new(program: &Program) -> Cfg {
+        Cfg::build(program.instructions(), program.labels().values().copied())
+    }
+
+    /// Builds the graph of `instructions`, starting blocks only where control
+    /// flow requires it.
+    pub fn from_instructions(instructions: &[Instruction]) -> Cfg {
+        Cfg::build(instructions, std::iter::empty())
+    }
+
+    fn build(instructions: &[Instruction], labels: impl Iterator<Item = usize>) -> Cfg {
+        let len = instructions.len();
+
+        let mut leaders = BTreeSet::from([0]);
+        leaders.extend(labels);
+        for (pc, instruction) in instructions.iter().enumerate() {
+            if let Some(Label::Instruction(target)) = instruction.target() {
+                leaders.insert(*target);
+            }
+            if ends_block(instruction) {
+                leaders.insert(pc + 1);
+            }
+        }
+        let starts = leaders
+            .into_iter()
+            .filter(|pc| *pc < len)
+            .collect::<Vec<usize>>();
+
+        let mut block_of = vec![0; len];
+        let mut blocks = Vec::with_capacity(starts.len());
+        for (i, start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).copied().unwrap_or(len);
+            block_of[*start..end].fill(i);
+            blocks.push(BasicBlock {
+                start: *start,
+                end,
+                successors: Vec::new(),
+                predecessors: Vec::new(),
+            });
+        }
+
+        for i in 0..blocks.len() {
+            let last = blocks[i].end - 1;
+            let instruction = &instructions[last];
+            let mut targets = Vec::new();
+            if let Some(Label::Instruction(target)) = instruction.target() {
+                targets.push(*target);
+            }
+            if falls_through(instruction) {
+                targets.push(last + 1);
+            }
+
+            for target in targets {
+                // Jumping to the end finishes the program like falling off it.
+                let Some(&successor) = block_of.get(target) else {
+                    continue;
+                };
+                if !blocks[i].successors.contains(&successor) {
+                    blocks[i].successors.push(successor);
+                    blocks[successor].predecessors.push(i);
+                }
+            }
+        }
+
+        let mut cfg = Cfg {
+            reachable: vec![false; blocks.len()],
+            idom: vec![None; blocks.len()],
+            blocks,
+            block_of,
+        };
+        cfg.compute_dominators();
+        cfg
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    /// The block containing the instruction at `pc`.
+    pub fn block_of(&self, pc: usize) -> Option<usize> {
+        self.block_of.get(pc).copied()
+    }
+
+    /// Whether the block can be reached from the entry block.
+    pub fn is_reachable(&self, block: usize) -> bool {
+        self.reachable[block]
+    }
+
+    /// The closest block other than `block` that every path from the entry to
+    /// it goes through. `None` for the entry block and unreachable blocks.
+    pub fn immediate_dominator(&self, block: usize) -> Option<usize> {
+        self.idom[block]
+    }
+
+    /// Whether every path from the entry to `b` goes through `a`. Blocks
+    /// dominate themselves.
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        if !self.reachable[b] {
+            return false;
+        }
+        let mut block = Some(b);
+        while let Some(current) = block {
+            if current == a {
+                return true;
+            }
+            block = self.idom[current];
+        }
+        false
+    }
+
+    /// Every natural loop, ordered by header. Loops sharing a header are merged.
+    pub fn loops(&self) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = Vec::new();
+        for (latch, block) in self.blocks.iter().enumerate() {
+            for &header in &block.successors {
+                if !self.dominates(header, latch) {
+                    continue;
+                }
+
+                let mut body = BTreeSet::from([header]);
+                let mut work = vec![latch];
+                while let Some(block) = work.pop() {
+                    if body.insert(block) {
+                        let predecessors = self.blocks[block].predecessors.iter();
+                        work.extend(predecessors.filter(|p| self.reachable[**p]));
+                    }
+                }
+
+                match loops.iter_mut().find(|l| l.header == header) {
+                    Some(existing) => {
+                        existing.latches.push(latch);
+                        body.extend(&existing.blocks);
+                        existing.blocks = body.into_iter().collect();
+                    }
+                    None => loops.push(Loop {
+                        header,
+                        latches: vec![latch],
+                        blocks: body.into_iter().collect(),
+                    }),
+                }
+            }
+        }
+        loops.sort_by_key(|l| l.header);
+        loops
+    }
+
+    /// Writes the graph in Graphviz DOT format, one box per block listing its
+    /// instructions. Edges into called blocks are dashed.
+    pub fn write_dot<W: Write>(&self, program: &Program, mut writer: W) -> io::Result<()> {
+        let names = disasm::target_names(program);
+        let instructions = program.instructions();
+
+        writeln!(
+            writer,
+            "digraph \"{}\" {{",
+            escape(&program.source_map().file().display().to_string())
+        )?;
+        writeln!(writer, "  node [shape=box, fontname=\"monospace\"];")?;
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            if let Some(name) = names.get(&block.start) {
+                label.push_str(&format!("{}:\\l", escape(name)));
+            }
+            for pc in block.instructions() {
+                let instruction = disasm::named(&instructions[pc], &names);
+                label.push_str(&format!("{}: {}\\l", pc, escape(&instruction.to_string())));
+            }
+            writeln!(writer, "  b{} [label=\"{}\"];", i, label)?;
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            let last = &instructions[block.end - 1];
+            for &successor in &block.successors {
+                let call = matches!(last, Instruction::Call(_))
+                    && self.blocks[successor].start != block.end;
+                if call {
+                    writeln!(writer, "  b{} -> b{} [style=dashed];", i, successor)?;
+                } else {
+                    writeln!(writer, "  b{} -> b{};", i, successor)?;
+                }
+            }
+        }
+
+        writeln!(writer, "}}")
+    }
+
+    fn compute_dominators(&mut self) {
+        if self.blocks.is_empty() {
+            return;
+        }
+
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (i, block) in order.iter().enumerate() {
+            position[*block] = i;
+            self.reachable[*block] = true;
+        }
+
+        // Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm".
+        // The entry is its own dominator while iterating.
+        let mut idom = vec![None; self.blocks.len()];
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order[1..] {
+                let mut new_idom = None;
+                for &predecessor in &self.blocks[block].predecessors {
+                    if idom[predecessor].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => predecessor,
+                        Some(current) => intersect(&idom, &position, predecessor, current),
+                    });
+                }
+                if idom[block] != new_idom {
+                    idom[block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        idom[0] = None;
+        self.idom = idom;
+    }
+
+    fn reverse_postorder(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::with_capacity(self.blocks.len());
+        // Each entry is a block and how many of its successors have been visited.
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.last_mut() {
+            match self.blocks[*block].successors.get(*next) {
+                Some(&successor) => {
+                    *next += 1;
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => {
+                    postorder.push(*block);
+                    stack.pop();
+                }
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+}
+
+fn intersect(idom: &[Option<usize>], position: &[usize], mut a: usize, mut b: usize) -> usize {
+    while a != b {
+        while position[a] > position[b] {
+            a = idom[a].expect("processed blocks have a dominator");
+        }
+        while position[b] > position[a] {
+            b = idom[b].expect("processed blocks have a dominator");
+        }
+    }
+    a
+}
+
+/// Where execution can go after `pc` without leaving the function it is in.
+/// Calls continue after the call and `ret` goes nowhere. Running off the end
+/// of the program is left out.
+pub(crate) fn local_successors(instructions: &[Instruction], pc: usize) -> Vec<usize> {
+    let instruction = &instructions[pc];
+    let mut successors = Vec::with_capacity(2);
+    if let (Some(Label::Instruction(target)), false) = (
+        instruction.target(),
+        matches!(instruction, Instruction::Call(_)),
+    ) {
+        successors.push(*target);
+    }
+    if falls_through(instruction) && !successors.contains(&(pc + 1)) {
+        successors.push(pc + 1);
+    }
+    successors.retain(|successor| *successor < instructions.len());
+    successors
+}
+
+/// Whether the next instruction starts a new block.
+fn ends_block(instruction: &Instruction) -> bool {
+    instruction.target().is_some()
+        || matches!(instruction, Instruction::Return | Instruction::Fault(_))
+}
+
+/// Whether execution can continue with the next instruction.
+fn falls_through(instruction: &Instruction) -> bool {
+    !matches!(
+        instruction,
+        Instruction::Jump(_) | Instruction::Return | Instruction::Fault(_)
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
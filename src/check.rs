@@ -0,0 +1,427 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::cfg::{local_successors, Cfg};
+use crate::instructions::{Destination, Instruction, Label, Source};
+use crate::program::Program;
+use crate::source::{SourceMap, Span};
+
+/// Something suspicious found by `check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// The instruction the lint is about, the first one for unreachable code.
+    pub pc: usize,
+    /// Index of the operand the lint is about, if it is about one.
+    pub operand: Option<usize>,
+    pub kind: LintKind,
+}
+
+impl Lint {
+    /// The source of the operand or instruction the lint is about, if known.
+    pub fn span(&self, source_map: &SourceMap) -> Option<Span> {
+        let instruction = source_map.instruction(self.pc)?;
+        let operand = self.operand.and_then(|i| instruction.operands.get(i));
+        Some(*operand.unwrap_or(&instruction.span))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// The register is read on a path where nothing has written it, so it is
+    /// whatever the runner was started with, usually 0.
+    ReadBeforeWrite(usize),
+    /// Nothing can read the value written to the register.
+    NeverRead(usize),
+    /// This many instructions in a row can never run.
+    Unreachable(usize),
+}
+
+/// Looks for registers read before anything writes them, values written that
+/// are never read and instructions that can never run. Sorted by instruction.
+///
+/// Registers accessed through a reference or by a host function could be any
+/// register. They count as writing every register and as reading every
+/// register, so lints are only reported when they are certain.
+pub fn check(program: &Program) -> Vec<Lint> {
+    let instructions = program.instructions();
+    let effects = instructions.iter().map(effects).collect::<Vec<Effects>>();
+
+    let mut lints = reads_before_writes(instructions, &effects);
+    lints.extend(unread_writes(instructions, &effects));
+    lints.extend(unreachable(program));
+    lints.sort_by_key(|lint| (lint.pc, lint.operand));
+    lints
+}
+
+/// A set of registers that can also be all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Registers {
+    All,
+    Only(BTreeSet<usize>),
+}
+
+impl Registers {
+    fn none() -> Registers {
+        Registers::Only(BTreeSet::new())
+    }
+
+    fn contains(&self, register: usize) -> bool {
+        match self {
+            Registers::All => true,
+            Registers::Only(registers) => registers.contains(&register),
+        }
+    }
+
+    fn insert(&mut self, register: usize) {
+        if let Registers::Only(registers) = self {
+            registers.insert(register);
+        }
+    }
+
+    fn remove(&mut self, register: usize) {
+        if let Registers::Only(registers) = self {
+            registers.remove(&register);
+        }
+    }
+
+    fn union(&mut self, other: &Registers) {
+        match (&mut *self, other) {
+            (Registers::All, _) => {}
+            (_, Registers::All) => *self = Registers::All,
+            (Registers::Only(a), Registers::Only(b)) => a.extend(b),
+        }
+    }
+
+    fn intersect(&mut self, other: &Registers) {
+        match (&mut *self, other) {
+            (_, Registers::All) => {}
+            (Registers::All, _) => *self = other.clone(),
+            (Registers::Only(a), Registers::Only(b)) => a.retain(|register| b.contains(register)),
+        }
+    }
+}
+
+/// The registers an instruction accesses, with the operand index of each direct access.
+#[derive(Debug, Default)]
+struct Effects {
+    reads: Vec<(usize, usize)>,
+    writes: Vec<(usize, usize)>,
+    /// Reads a register that is only known at runtime.
+    reads_any: bool,
+    /// Writes registers that are only known at runtime.
+    writes_any: bool,
+}
+
+impl Effects {
+    fn read(&mut self, source: &Source, operand: usize) {
+        match source {
+            Source::Address(register) => self.reads.push((*register, operand)),
+            Source::Reference(register) => {
+                self.reads.push((*register, operand));
+                self.reads_any = true;
+            }
+            Source::Data(_) => {}
+        }
+    }
+
+    fn write(&mut self, destination: &Destination, operand: usize) {
+        match destination {
+            Destination::Address(register) => self.writes.push((*register, operand)),
+            Destination::Reference(register) => {
+                self.reads.push((*register, operand));
+                self.writes_any = true;
+            }
+        }
+    }
+}
+
+fn effects(instruction: &Instruction) -> Effects {
+    let mut effects = Effects::default();
+    match instruction {
+        Instruction::Set(a, d) => {
+            effects.read(a, 0);
+            effects.write(d, 1);
+        }
+        Instruction::Input(d) | Instruction::Time(d) => effects.write(d, 0),
+        Instruction::CharInput(d, size) => {
+            effects.read(size, 1);
+            // Writes one register per character read, however many that is.
+            match d {
+                Destination::Address(_) => effects.writes_any = true,
+                Destination::Reference(_) => effects.write(d, 0),
+            }
+        }
+        Instruction::Output(a) | Instruction::CharOutput(a) => effects.read(a, 0),
+        Instruction::Add(a, b, d)
+        | Instruction::Subtract(a, b, d)
+        | Instruction::Multiply(a, b, d)
+        | Instruction::Divide(a, b, d)
+        | Instruction::Modulo(a, b, d) => {
+            effects.read(a, 0);
+            effects.read(b, 1);
+            effects.write(d, 2);
+        }
+        Instruction::JumpGreaterThan(a, b, _)
+        | Instruction::JumpEqual(a, b, _)
+        | Instruction::JumpLessThan(a, b, _) => {
+            effects.read(a, 0);
+            effects.read(b, 1);
+        }
+        Instruction::HostCall(_) => {
+            effects.reads_any = true;
+            effects.writes_any = true;
+        }
+        Instruction::Syscall(d, number, a1, a2, a3, a4, a5, a6) => {
+            effects.read(number, 1);
+            for (i, arg) in [a1, a2, a3, a4, a5, a6].into_iter().enumerate() {
+                if let Some(arg) = arg {
+                    effects.read(arg, i + 2);
+                }
+            }
+            effects.write(d, 0);
+        }
+        Instruction::Return
+        | Instruction::Jump(_)
+        | Instruction::Call(_)
+        | Instruction::Fault(_) => {}
+    }
+    effects
+}
+
+fn target(instruction: &Instruction) -> Option<usize> {
+    match instruction.target() {
+        Some(Label::Instruction(target)) => Some(*target),
+        _ => None,
+    }
+}
+
+/// For every instruction, the registers written on every path from `start`
+/// to it, `None` if it isn't reached. Calls add what the called function
+/// always writes, and with `enter_calls` the function is analyzed too.
+fn written(
+    instructions: &[Instruction],
+    effects: &[Effects],
+    start: usize,
+    summaries: &HashMap<usize, Registers>,
+    enter_calls: bool,
+) -> Vec<Option<Registers>> {
+    let mut states: Vec<Option<Registers>> = vec![None; instructions.len()];
+    let mut work = Vec::new();
+    if start < instructions.len() {
+        states[start] = Some(Registers::none());
+        work.push(start);
+    }
+
+    while let Some(pc) = work.pop() {
+        let Some(state) = states[pc].clone() else {
+            continue;
+        };
+
+        let mut after = state.clone();
+        if effects[pc].writes_any {
+            after = Registers::All;
+        }
+        for (register, _) in &effects[pc].writes {
+            after.insert(*register);
+        }
+
+        let mut successors = local_successors(instructions, pc)
+            .into_iter()
+            .map(|successor| (successor, after.clone()))
+            .collect::<Vec<_>>();
+        if let Instruction::Call(_) = instructions[pc] {
+            if let Some(callee) = target(&instructions[pc]) {
+                if let (Some((_, next)), Some(summary)) =
+                    (successors.first_mut(), summaries.get(&callee))
+                {
+                    next.union(summary);
+                }
+                if enter_calls {
+                    successors.push((callee, after.clone()));
+                }
+            }
+        }
+
+        for (successor, incoming) in successors {
+            let merged = match &states[successor] {
+                None => incoming,
+                Some(existing) => {
+                    let mut merged = existing.clone();
+                    merged.intersect(&incoming);
+                    merged
+                }
+            };
+            if states[successor].as_ref() != Some(&merged) {
+                states[successor] = Some(merged);
+                work.push(successor);
+            }
+        }
+    }
+    states
+}
+
+/// What each called function writes on every path to one of its `ret`s.
+fn summaries(instructions: &[Instruction], effects: &[Effects]) -> HashMap<usize, Registers> {
+    let callees = instructions
+        .iter()
+        .filter(|instruction| matches!(instruction, Instruction::Call(_)))
+        .filter_map(target)
+        .collect::<BTreeSet<usize>>();
+
+    // Start from functions writing everything and narrow down, which also
+    // settles functions that call each other.
+    let mut summaries = callees
+        .iter()
+        .map(|callee| (*callee, Registers::All))
+        .collect::<HashMap<usize, Registers>>();
+    loop {
+        let mut changed = false;
+        for callee in &callees {
+            let states = written(instructions, effects, *callee, &summaries, false);
+            let mut summary = Registers::All;
+            for (pc, state) in states.iter().enumerate() {
+                if let (Instruction::Return, Some(state)) = (&instructions[pc], state) {
+                    summary.intersect(state);
+                }
+            }
+            if summaries[callee] != summary {
+                summaries.insert(*callee, summary);
+                changed = true;
+            }
+        }
+        if !changed {
+            return summaries;
+        }
+    }
+}
+
+fn reads_before_writes(instructions: &[Instruction], effects: &[Effects]) -> Vec<Lint> {
+    let summaries = summaries(instructions, effects);
+    let states = written(instructions, effects, 0, &summaries, true);
+
+    let mut lints = Vec::new();
+    for (pc, state) in states.iter().enumerate() {
+        let Some(state) = state else {
+            continue;
+        };
+        let mut reported = BTreeSet::new();
+        for (register, operand) in &effects[pc].reads {
+            if !state.contains(*register) && reported.insert(*register) {
+                lints.push(Lint {
+                    pc,
+                    operand: Some(*operand),
+                    kind: LintKind::ReadBeforeWrite(*register),
+                });
+            }
+        }
+    }
+    lints
+}
+
+fn unread_writes(instructions: &[Instruction], effects: &[Effects]) -> Vec<Lint> {
+    let len = instructions.len();
+    let return_sites = instructions
+        .iter()
+        .enumerate()
+        .filter(|(_, instruction)| matches!(instruction, Instruction::Call(_)))
+        .map(|(pc, _)| pc + 1)
+        .filter(|pc| *pc < len)
+        .collect::<Vec<usize>>();
+
+    // Any `ret` may return to any call, and a call goes into the function.
+    let successors = |pc: usize| -> Vec<usize> {
+        match &instructions[pc] {
+            Instruction::Return => return_sites.clone(),
+            Instruction::Call(_) => target(&instructions[pc])
+                .filter(|callee| *callee < len)
+                .into_iter()
+                .collect(),
+            _ => local_successors(instructions, pc),
+        }
+    };
+    let mut predecessors = vec![Vec::new(); len];
+    for pc in 0..len {
+        for successor in successors(pc) {
+            predecessors[successor].push(pc);
+        }
+    }
+
+    // Registers that may be read after each instruction, before being written again.
+    let mut live_out = vec![Registers::none(); len];
+    let mut work = (0..len).collect::<Vec<usize>>();
+    while let Some(pc) = work.pop() {
+        let mut live_in = live_out[pc].clone();
+        for (register, _) in &effects[pc].writes {
+            live_in.remove(*register);
+        }
+        if effects[pc].reads_any {
+            live_in = Registers::All;
+        }
+        for (register, _) in &effects[pc].reads {
+            live_in.insert(*register);
+        }
+
+        for &predecessor in &predecessors[pc] {
+            let mut merged = live_out[predecessor].clone();
+            merged.union(&live_in);
+            if merged != live_out[predecessor] {
+                live_out[predecessor] = merged;
+                work.push(predecessor);
+            }
+        }
+    }
+
+    let mut lints = Vec::new();
+    for pc in 0..len {
+        for (register, operand) in &effects[pc].writes {
+            if !live_out[pc].contains(*register) {
+                lints.push(Lint {
+                    pc,
+                    operand: Some(*operand),
+                    kind: LintKind::NeverRead(*register),
+                });
+            }
+        }
+    }
+    lints
+}
+
+fn unreachable(program: &Program) -> Vec<Lint> {
+    let cfg = Cfg::new(program);
+    let mut lints: Vec<Lint> = Vec::new();
+    for (i, block) in cfg.blocks().iter().enumerate() {
+        if cfg.is_reachable(i) {
+            continue;
+        }
+        let count = block.end - block.start;
+        // Blocks only split by a label are reported together.
+        match lints.last_mut() {
+            Some(Lint {
+                pc,
+                kind: LintKind::Unreachable(previous),
+                ..
+            }) if *pc + *previous == block.start => *previous += count,
+            _ => lints.push(Lint {
+                pc: block.start,
+                operand: None,
+                kind: LintKind::Unreachable(count),
+            }),
+        }
+    }
+    lints
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LintKind::ReadBeforeWrite(register) => {
+                write!(f, "Register {} may be read before it is written", register)
+            }
+            LintKind::NeverRead(register) => {
+                write!(f, "Value written to register {} is never read", register)
+            }
+            LintKind::Unreachable(1) => write!(f, "Unreachable instruction"),
+            LintKind::Unreachable(count) => write!(f, "{} unreachable instructions", count),
+        }
+    }
+}
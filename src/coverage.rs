@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::instructions::Instruction;
+use crate::observer::{Observer, State};
+use crate::program::Program;
+
+/// Records which instructions ran and which way each conditional jump went, see
+/// `Coverage::summary` and `Coverage::write_lcov`.
+pub struct Coverage<'a> {
+    program: &'a Program,
+    counts: Vec<u64>,
+    /// Times each instruction jumped and fell through, only used for `jgt`, `jeq` and `jlt`.
+    branches: Vec<[u64; 2]>,
+}
+
+/// How often the instructions on a source line were executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCoverage {
+    pub line: usize,
+    pub count: u64,
+}
+
+/// How often a conditional jump was taken and not taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BranchCoverage {
+    pub pc: usize,
+    pub line: usize,
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+impl BranchCoverage {
+    pub fn executed(&self) -> bool {
+        self.taken + self.not_taken > 0
+    }
+
+    /// Whether the jump went both ways.
+    pub fn covered(&self) -> bool {
+        self.taken > 0 && self.not_taken > 0
+    }
+}
+
+impl<'a> Coverage<'a> {
+    pub fn new(program: &'a Program) -> Coverage<'a> {
+        let len = program.instructions().len();
+        Coverage {
+            program,
+            counts: vec![0; len],
+            branches: vec![[0; 2]; len],
+        }
+    }
+
+    /// Every source line with at least one instruction on it, in order.
+    /// Instructions without a source location, as in bytecode, are left out.
+    pub fn lines(&self) -> Vec<LineCoverage> {
+        let mut lines = BTreeMap::<usize, u64>::new();
+        for (pc, count) in self.counts.iter().enumerate() {
+            if let Some(line) = self.line(pc) {
+                *lines.entry(line).or_default() += count;
+            }
+        }
+
+        lines
+            .into_iter()
+            .map(|(line, count)| LineCoverage { line, count })
+            .collect()
+    }
+
+    /// Every `jgt`, `jeq` and `jlt` with a source location, in order.
+    pub fn branches(&self) -> Vec<BranchCoverage> {
+        self.program
+            .instructions()
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| is_branch(instruction))
+            .filter_map(|(pc, _)| {
+                Some(BranchCoverage {
+                    pc,
+                    line: self.line(pc)?,
+                    taken: self.branches[pc][0],
+                    not_taken: self.branches[pc][1],
+                })
+            })
+            .collect()
+    }
+
+    /// Line and branch totals followed by the lines that never ran and the
+    /// branches that only went one way.
+    pub fn summary(&self) -> String {
+        let lines = self.lines();
+        let branches = self.branches();
+        let lines_hit = lines.iter().filter(|line| line.count > 0).count();
+        let branches_hit = branches.iter().filter(|branch| branch.covered()).count();
+
+        let mut summary = format!(
+            "Coverage of {}\n  Lines:    {}\n  Branches: {}\n",
+            self.program.source_map().file().display(),
+            ratio(lines_hit, lines.len()),
+            ratio(branches_hit, branches.len())
+        );
+
+        let missed = lines.iter().filter(|line| line.count == 0);
+        for (i, line) in missed.enumerate() {
+            if i == 0 {
+                summary.push_str("\nNever executed:\n");
+            }
+            let _ = writeln!(summary, "{}", self.source_line(line.line));
+        }
+
+        let partial = branches
+            .iter()
+            .filter(|branch| branch.executed() && !branch.covered());
+        for (i, branch) in partial.enumerate() {
+            if i == 0 {
+                summary.push_str("\nOnly went one way:\n");
+            }
+            let direction = if branch.taken > 0 {
+                "always jumped"
+            } else {
+                "never jumped"
+            };
+            let _ = writeln!(
+                summary,
+                "{}  ({})",
+                self.source_line(branch.line),
+                direction
+            );
+        }
+
+        summary
+    }
+
+    /// Writes an lcov tracefile for the file the program was parsed from. Every
+    /// conditional jump is a block with branch 0 for jumping and 1 for falling through.
+    pub fn write_lcov<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "TN:")?;
+        writeln!(writer, "SF:{}", self.program.source_map().file().display())?;
+
+        let branches = self.branches();
+        for branch in &branches {
+            for (i, count) in [branch.taken, branch.not_taken].iter().enumerate() {
+                if branch.executed() {
+                    writeln!(writer, "BRDA:{},{},{},{}", branch.line, branch.pc, i, count)?;
+                } else {
+                    writeln!(writer, "BRDA:{},{},{},-", branch.line, branch.pc, i)?;
+                }
+            }
+        }
+        let branches_hit = branches
+            .iter()
+            .map(|branch| (branch.taken > 0) as usize + (branch.not_taken > 0) as usize)
+            .sum::<usize>();
+        writeln!(writer, "BRF:{}", branches.len() * 2)?;
+        writeln!(writer, "BRH:{}", branches_hit)?;
+
+        let lines = self.lines();
+        for line in &lines {
+            writeln!(writer, "DA:{},{}", line.line, line.count)?;
+        }
+        writeln!(writer, "LF:{}", lines.len())?;
+        writeln!(
+            writer,
+            "LH:{}",
+            lines.iter().filter(|line| line.count > 0).count()
+        )?;
+        writeln!(writer, "end_of_record")
+    }
+
+    fn line(&self, pc: usize) -> Option<usize> {
+        self.program
+            .source_map()
+            .instruction(pc)
+            .map(|span| span.span.line)
+    }
+
+    fn source_line(&self, line: usize) -> String {
+        let contents = self.program.source_map().line(line).unwrap_or("");
+        format!("{:>6} | {}", line, contents.trim())
+    }
+}
+
+impl Observer for Coverage<'_> {
+    fn before_instruction(&mut self, pc: usize, instruction: &Instruction, state: State) {
+        self.counts[pc] += 1;
+
+        let (a, b) = match instruction {
+            Instruction::JumpGreaterThan(a, b, _)
+            | Instruction::JumpEqual(a, b, _)
+            | Instruction::JumpLessThan(a, b, _) => (a, b),
+            _ => return,
+        };
+        // Reading out of bounds fails the instruction before it can jump.
+        let (Some(a), Some(b)) = (state.value(a), state.value(b)) else {
+            return;
+        };
+        let jumps = match instruction {
+            Instruction::JumpGreaterThan(..) => a > b,
+            Instruction::JumpEqual(..) => a == b,
+            _ => a < b,
+        };
+        self.branches[pc][usize::from(!jumps)] += 1;
+    }
+
+    fn observes_writes(&self) -> bool {
+        false
+    }
+}
+
+fn is_branch(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::JumpGreaterThan(..)
+            | Instruction::JumpEqual(..)
+            | Instruction::JumpLessThan(..)
+    )
+}
+
+fn ratio(hit: usize, total: usize) -> String {
+    if total == 0 {
+        return "0/0".to_string();
+    }
+    format!(
+        "{}/{} ({:.1}%)",
+        hit,
+        total,
+        hit as f64 * 100.0 / total as f64
+    )
+}
@@ -0,0 +1,52 @@
+use std::io::{self, BufRead, Write};
+
+use yaul::debugger::Command;
+use yaul::{Debugger, Runner};
+
+use crate::args::DebugArgs;
+use crate::load;
+
+/// Reads debugger commands from stdin until `quit` or end of input.
+pub fn debug(args: &DebugArgs) {
+    let program = load(&args.file).0;
+    let mut runner = Runner::builder().registers(args.registers as usize).build();
+    for watchpoint in &args.watch {
+        runner.watch(*watchpoint);
+    }
+    let mut debugger = Debugger::new(&program, runner);
+
+    for breakpoint in &args.breakpoints {
+        let command = breakpoint.parse().map(Command::Break);
+        match command {
+            Ok(command) => println!("{}", debugger.execute(&command)),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+    println!("{}", debugger.execute(&Command::Where));
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("(yaul) ");
+        let _ = io::stdout().flush();
+
+        line.clear();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Failed to read command: {}", e);
+                std::process::exit(1);
+            }
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match line.parse::<Command>() {
+            Ok(Command::Quit) => break,
+            Ok(command) => println!("{}", debugger.execute(&command)),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+}
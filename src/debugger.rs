@@ -0,0 +1,550 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use crate::io::{Io, StdIo};
+use crate::observer::Observer;
+use crate::program::Program;
+use crate::range::parse_range;
+use crate::runner::{ExitStatus, Runner, RuntimeError};
+use crate::watch::{Access, WatchHit, Watchpoint};
+
+/// Drives a `Runner` one instruction at a time, see `Command` for what it can do.
+///
+/// The runner keeps a history so execution can also run backwards.
+pub struct Debugger<'a, I = StdIo, O = ()> {
+    program: &'a Program,
+    runner: Runner<I, O>,
+    breakpoints: BTreeSet<usize>,
+    /// Whether the program exited or failed and has to be restarted.
+    finished: bool,
+}
+
+/// Why execution stopped.
+#[derive(Debug)]
+pub enum Stop {
+    /// The requested step finished.
+    Step,
+    /// A breakpoint was hit, before executing the instruction at `pc`.
+    Breakpoint(usize),
+    /// A watched register was accessed by the instruction before the current one.
+    /// When running backwards, the current instruction is the one that wrote it.
+    Watchpoint(WatchHit),
+    /// Running backwards reached the start of the program.
+    Start,
+    Exited(ExitStatus),
+    Error(RuntimeError),
+}
+
+/// A place to break, either a label or a 1-based source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Label(String),
+    Line(usize),
+}
+
+/// Registers to print, `5`, `3..7`, `3..=7`, `3..`, `..7` or `&5` for the register that
+/// register 5 points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registers {
+    Range(usize, usize),
+    Deref(usize),
+}
+
+/// A register to modify, `5` or `&5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    Address(usize),
+    Reference(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Break(Location),
+    Delete(Location),
+    Breakpoints,
+    Watch(Watchpoint),
+    Unwatch(Watchpoint),
+    Step,
+    Next,
+    Finish,
+    Continue,
+    ReverseStep,
+    ReverseContinue,
+    Goto(usize),
+    Print(Registers),
+    Set(Register, i64),
+    Stack,
+    Where,
+    Restart,
+    Help,
+    Quit,
+}
+
+pub const HELP: &str = "\
+break <label|line>       b   stop before the instruction at a label or line
+delete <label|line>      d   remove a breakpoint
+watch <registers>[:rw]       stop after `5` or `3..=7` is read (r) or written (w, the default)
+unwatch <registers>[:rw]     remove a watchpoint
+breakpoints                  list breakpoints and watchpoints
+step                     s   execute one instruction
+next                     n   execute one instruction, stepping over calls
+finish                   f   run until the current call returns
+continue                 c   run until a breakpoint or the end of the program
+rstep                    rs  undo the last instruction
+rcontinue                rc  run backwards to a breakpoint or the last write of a watched register
+goto <count>                 go back or forward to the state after `count` instructions
+print <registers>        p   print `5`, `3..7`, `3..=7`, `3..` or `&5` (the register 5 points to)
+set <register> <value>       write `5` or `&5`
+stack                    bt  print the return addresses of the active calls
+where                    w   print the current instruction and how many have run
+restart                      run the program again from the start
+help                     h   print this help
+quit                     q   stop debugging";
+
+impl<'a, I: Io, O: Observer> Debugger<'a, I, O> {
+    pub fn new(program: &'a Program, mut runner: Runner<I, O>) -> Self {
+        runner.set_history(true);
+        runner.reset();
+        Debugger {
+            program,
+            runner,
+            breakpoints: BTreeSet::new(),
+            finished: false,
+        }
+    }
+
+    pub fn runner(&self) -> &Runner<I, O> {
+        &self.runner
+    }
+
+    /// Instruction indices with a breakpoint.
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    /// Adds a breakpoint and returns the instruction it was placed on.
+    pub fn add_breakpoint(&mut self, location: &Location) -> Result<usize, String> {
+        let pc = self.resolve(location)?;
+        self.breakpoints.insert(pc);
+        Ok(pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, location: &Location) -> Result<usize, String> {
+        let pc = self.resolve(location)?;
+        if self.breakpoints.remove(&pc) {
+            Ok(pc)
+        } else {
+            Err(format!("No breakpoint at instruction {}", pc))
+        }
+    }
+
+    /// Executes one instruction.
+    pub fn step(&mut self) -> Result<Stop, String> {
+        self.check_running()?;
+        Ok(self.single_step().unwrap_or(Stop::Step))
+    }
+
+    /// Executes one instruction, running any function it calls to completion.
+    pub fn step_over(&mut self) -> Result<Stop, String> {
+        let depth = self.runner.stack().len();
+        self.run_while(|runner| runner.stack().len() > depth)
+    }
+
+    /// Runs until the current function returns.
+    pub fn step_out(&mut self) -> Result<Stop, String> {
+        let depth = self.runner.stack().len();
+        if depth == 0 {
+            return Err("Not inside a call".to_string());
+        }
+        self.run_while(|runner| runner.stack().len() >= depth)
+    }
+
+    /// Runs until a breakpoint is hit or the program ends.
+    pub fn resume(&mut self) -> Result<Stop, String> {
+        self.run_while(|_| true)
+    }
+
+    /// Undoes the last instruction.
+    pub fn reverse_step(&mut self) -> Result<Stop, String> {
+        match self.runner.step_back() {
+            Some(_) => {
+                self.finished = false;
+                Ok(Stop::Step)
+            }
+            None => Err("Already at the start of the program".to_string()),
+        }
+    }
+
+    /// Runs backwards until a breakpoint or the instruction that last wrote a watched register.
+    pub fn reverse_continue(&mut self) -> Result<Stop, String> {
+        if self.executed() == 0 {
+            return Err("Already at the start of the program".to_string());
+        }
+        self.finished = false;
+
+        loop {
+            let Some(last) = self.runner.history().and_then(|history| history.last()) else {
+                return Ok(Stop::Start);
+            };
+            let watched = last.writes.iter().rev().find(|(register, _)| {
+                self.runner
+                    .watchpoints()
+                    .iter()
+                    .any(|watchpoint| watchpoint.matches(*register, Access::Write))
+            });
+            let hit = watched.map(|(register, old)| WatchHit {
+                pc: last.pc,
+                register: *register,
+                access: Access::Write,
+                old: *old,
+                value: self.runner.registers()[*register],
+            });
+
+            self.runner.step_back();
+            if let Some(hit) = hit {
+                return Ok(Stop::Watchpoint(hit));
+            }
+            let pc = self.runner.pc();
+            if self.breakpoints.contains(&pc) {
+                return Ok(Stop::Breakpoint(pc));
+            }
+        }
+    }
+
+    /// Moves to the state after `count` instructions have run since the start,
+    /// undoing instructions or executing them again as needed. Instructions
+    /// executed again get the same input as the first time. Watchpoints are
+    /// ignored on the way.
+    pub fn goto(&mut self, count: usize) -> Result<Stop, String> {
+        while self.executed() > count {
+            self.reverse_step()?;
+        }
+        while self.executed() < count {
+            self.check_running()?;
+            match self.single_step() {
+                None | Some(Stop::Watchpoint(_)) => {}
+                Some(stop) => return Ok(stop),
+            }
+        }
+        Ok(Stop::Step)
+    }
+
+    /// Number of instructions executed since the start of the program.
+    pub fn executed(&self) -> usize {
+        self.runner.history().map_or(0, |history| history.len())
+    }
+
+    /// Starts the program over with zeroed registers.
+    pub fn restart(&mut self) {
+        self.runner.reset();
+        self.finished = false;
+    }
+
+    pub fn read(&self, register: usize) -> Result<i64, String> {
+        self.runner
+            .registers()
+            .get(register)
+            .copied()
+            .ok_or_else(|| format!("Register out of bounds: {}", register))
+    }
+
+    pub fn write(&mut self, register: Register, value: i64) -> Result<usize, String> {
+        let register = match register {
+            Register::Address(i) => i,
+            Register::Reference(i) => self.read(i)? as usize,
+        };
+        if self.runner.set_register(register, value) {
+            Ok(register)
+        } else {
+            Err(format!("Register out of bounds: {}", register))
+        }
+    }
+
+    /// Executes `command` and describes the result.
+    pub fn execute(&mut self, command: &Command) -> String {
+        let result = match command {
+            Command::Break(location) => self
+                .add_breakpoint(location)
+                .map(|pc| format!("Breakpoint at {}", self.describe(pc))),
+            Command::Delete(location) => self
+                .remove_breakpoint(location)
+                .map(|pc| format!("Removed breakpoint at instruction {}", pc)),
+            Command::Breakpoints
+                if self.breakpoints.is_empty() && self.runner.watchpoints().is_empty() =>
+            {
+                Ok("No breakpoints".to_string())
+            }
+            Command::Breakpoints => Ok(self
+                .breakpoints
+                .iter()
+                .map(|pc| self.describe(*pc))
+                .chain(
+                    self.runner
+                        .watchpoints()
+                        .iter()
+                        .map(|watchpoint| format!("watch {}", watchpoint)),
+                )
+                .collect::<Vec<String>>()
+                .join("\n")),
+            Command::Watch(watchpoint) => {
+                self.runner.watch(*watchpoint);
+                Ok(format!("Watching {}", watchpoint))
+            }
+            Command::Unwatch(watchpoint) if self.runner.unwatch(watchpoint) => {
+                Ok(format!("Removed watchpoint {}", watchpoint))
+            }
+            Command::Unwatch(watchpoint) => Err(format!("No watchpoint {}", watchpoint)),
+            Command::Step => self.step().map(|stop| self.describe_stop(stop)),
+            Command::Next => self.step_over().map(|stop| self.describe_stop(stop)),
+            Command::Finish => self.step_out().map(|stop| self.describe_stop(stop)),
+            Command::Continue => self.resume().map(|stop| self.describe_stop(stop)),
+            Command::ReverseStep => self.reverse_step().map(|stop| self.describe_stop(stop)),
+            Command::ReverseContinue => {
+                self.reverse_continue().map(|stop| self.describe_stop(stop))
+            }
+            Command::Goto(count) => self.goto(*count).map(|stop| self.describe_stop(stop)),
+            Command::Print(registers) => self.print(*registers),
+            Command::Set(register, value) => self
+                .write(*register, *value)
+                .map(|register| format!("r{} = {}", register, value)),
+            Command::Stack if self.runner.stack().is_empty() => Ok("Not inside a call".to_string()),
+            Command::Stack => Ok(self
+                .runner
+                .stack()
+                .iter()
+                .rev()
+                .map(|pc| format!("returns to {}", self.describe(*pc)))
+                .collect::<Vec<String>>()
+                .join("\n")),
+            Command::Where => Ok(format!(
+                "{}\n{} instructions executed",
+                self.describe(self.runner.pc()),
+                self.executed()
+            )),
+            Command::Restart => {
+                self.restart();
+                Ok(format!("Restarted at {}", self.describe(self.runner.pc())))
+            }
+            Command::Help => Ok(HELP.to_string()),
+            Command::Quit => Ok(String::new()),
+        };
+
+        result.unwrap_or_else(|e| format!("Error: {}", e))
+    }
+
+    fn check_running(&self) -> Result<(), String> {
+        if self.finished {
+            Err("The program has finished, use `restart` to run it again".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Executes one instruction and returns why execution stopped, if it did.
+    fn single_step(&mut self) -> Option<Stop> {
+        let stop = match self.runner.step(self.program) {
+            Ok(None) => return None,
+            Ok(Some(ExitStatus::Watchpoint(hit))) => return Some(Stop::Watchpoint(hit)),
+            Ok(Some(status)) => Stop::Exited(status),
+            Err(e) => Stop::Error(e),
+        };
+        self.finished = !matches!(stop, Stop::Exited(ExitStatus::OutOfFuel));
+        Some(stop)
+    }
+
+    /// Steps at least once, then for as long as `keep_going` holds and no breakpoint is hit.
+    fn run_while(&mut self, keep_going: impl Fn(&Runner<I, O>) -> bool) -> Result<Stop, String> {
+        self.check_running()?;
+
+        loop {
+            if let Some(stop) = self.single_step() {
+                return Ok(stop);
+            }
+            let pc = self.runner.pc();
+            if self.breakpoints.contains(&pc) {
+                return Ok(Stop::Breakpoint(pc));
+            }
+            if !keep_going(&self.runner) {
+                return Ok(Stop::Step);
+            }
+        }
+    }
+
+    fn resolve(&self, location: &Location) -> Result<usize, String> {
+        match location {
+            Location::Label(label) => self
+                .program
+                .labels()
+                .get(label)
+                .copied()
+                .ok_or_else(|| format!("Unknown label: {}", label)),
+            Location::Line(line) => (0..self.program.instructions().len())
+                .find(|pc| {
+                    self.program
+                        .source_map()
+                        .instruction(*pc)
+                        .is_some_and(|span| span.span.line >= *line)
+                })
+                .ok_or_else(|| format!("No instruction on or after line {}", line)),
+        }
+    }
+
+    fn print(&self, registers: Registers) -> Result<String, String> {
+        match registers {
+            Registers::Range(start, end) => {
+                // Open ranges stop at the last register.
+                let end = end
+                    .min(self.runner.registers().len().saturating_sub(1))
+                    .max(start);
+                let mut output = String::new();
+                for register in start..=end {
+                    if register > start {
+                        output.push('\n');
+                    }
+                    let _ = write!(output, "r{} = {}", register, self.read(register)?);
+                }
+                Ok(output)
+            }
+            Registers::Deref(register) => {
+                let target = self.read(register)?;
+                let value = self.read(target as usize)?;
+                Ok(format!("&{} -> r{} = {}", register, target, value))
+            }
+        }
+    }
+
+    /// `instruction 3 (file:4): add 1 2 3`
+    fn describe(&self, pc: usize) -> String {
+        let source_map = self.program.source_map();
+        match source_map.instruction(pc) {
+            Some(span) => format!(
+                "instruction {} ({}:{}): {}",
+                pc,
+                source_map.file().display(),
+                span.span.line,
+                source_map.line(span.span.line).unwrap_or("").trim()
+            ),
+            None => match self.program.instructions().get(pc) {
+                // Programs loaded from bytecode have no source to show.
+                Some(instruction) => format!("instruction {}: {}", pc, instruction),
+                None => format!("instruction {} (end of program)", pc),
+            },
+        }
+    }
+
+    fn describe_stop(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Step => format!("Stopped at {}", self.describe(self.runner.pc())),
+            Stop::Breakpoint(pc) => format!("Breakpoint hit at {}", self.describe(pc)),
+            Stop::Watchpoint(hit) => format!(
+                "Watchpoint: {} by {}\nStopped at {}",
+                hit,
+                self.describe(hit.pc),
+                self.describe(self.runner.pc())
+            ),
+            Stop::Start => format!("Reached the start at {}", self.describe(self.runner.pc())),
+            Stop::Exited(ExitStatus::OutOfFuel) => "Out of fuel".to_string(),
+            Stop::Exited(status) => format!("Program exited ({:?})", status),
+            Stop::Error(e) => {
+                let mut output = format!("Runtime error: {}", e);
+                if let Some(span) = self.program.source_map().instruction(e.pc) {
+                    let _ = write!(output, "\n{}", self.program.source_map().excerpt(span.span));
+                }
+                output
+            }
+        }
+    }
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(line) => Ok(Location::Line(line)),
+            Err(_) if s.is_empty() => Err("Expected a label or line".to_string()),
+            Err(_) => Ok(Location::Label(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for Registers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(register) = s.strip_prefix('&') {
+            return Ok(Registers::Deref(parse_register(register)?));
+        }
+
+        let (start, end) = parse_range(s, "register")?;
+        Ok(Registers::Range(start, end))
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('&') {
+            Some(register) => Ok(Register::Reference(parse_register(register)?)),
+            None => Ok(Register::Address(parse_register(s)?)),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = s.split_whitespace().collect::<Vec<&str>>();
+        let Some((name, args)) = words.split_first() else {
+            return Err("Expected a command, try `help`".to_string());
+        };
+
+        let arg = |i: usize| {
+            args.get(i)
+                .copied()
+                .ok_or_else(|| format!("`{}` is missing an argument, try `help`", name))
+        };
+
+        let command = match *name {
+            "break" | "b" => Command::Break(arg(0)?.parse()?),
+            "delete" | "d" => Command::Delete(arg(0)?.parse()?),
+            "breakpoints" => Command::Breakpoints,
+            "watch" => Command::Watch(arg(0)?.parse()?),
+            "unwatch" => Command::Unwatch(arg(0)?.parse()?),
+            "step" | "s" => Command::Step,
+            "next" | "n" => Command::Next,
+            "finish" | "f" => Command::Finish,
+            "continue" | "c" => Command::Continue,
+            "rstep" | "rs" => Command::ReverseStep,
+            "rcontinue" | "rc" => Command::ReverseContinue,
+            "goto" => Command::Goto(
+                arg(0)?
+                    .parse()
+                    .map_err(|_| format!("Invalid count: {}", arg(0).unwrap_or("")))?,
+            ),
+            "print" | "p" => Command::Print(arg(0)?.parse()?),
+            "set" => Command::Set(
+                arg(0)?.parse()?,
+                arg(1)?
+                    .parse()
+                    .map_err(|_| format!("Invalid value: {}", arg(1).unwrap_or("")))?,
+            ),
+            "stack" | "bt" => Command::Stack,
+            "where" | "w" => Command::Where,
+            "restart" => Command::Restart,
+            "help" | "h" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(format!("Unknown command: {}, try `help`", name)),
+        };
+
+        Ok(command)
+    }
+}
+
+fn parse_register(text: &str) -> Result<usize, String> {
+    text.parse::<usize>()
+        .map_err(|_| format!("Invalid register: {}", text))
+}
@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::instructions::{Instruction, Label};
+use crate::program::Program;
+
+/// Prints `program` in canonical YAUL syntax, one instruction per line.
+///
+/// Every jump and call target gets a label line: the program's own label if it
+/// has one, otherwise a synthesized `L<index>`. Parsing the output gives back
+/// the same instructions.
+pub fn disassemble(program: &Program) -> String {
+    let names = target_names(program);
+
+    let mut output = String::new();
+    let len = program.instructions().len();
+    for pc in 0..=len {
+        if let Some(name) = names.get(&pc) {
+            if !output.is_empty() {
+                output.push('\n');
+            }
+            let _ = writeln!(output, "{}:", name);
+        }
+        if let Some(instruction) = program.instructions().get(pc) {
+            let _ = writeln!(output, "{}", named(instruction, &names));
+        }
+    }
+    output
+}
+
+/// Picks a name for every instruction index that is jumped to or called.
+pub(crate) fn target_names(program: &Program) -> BTreeMap<usize, String> {
+    let mut names = BTreeMap::new();
+    for instruction in program.instructions() {
+        if let Some(Label::Instruction(pc)) = instruction.target() {
+            names.entry(*pc).or_insert_with(|| name(program, *pc));
+        }
+    }
+    names
+}
+
+fn name(program: &Program, pc: usize) -> String {
+    let labels = program.labels();
+    let own = labels
+        .iter()
+        .filter(|(_, target)| **target == pc)
+        .map(|(name, _)| name)
+        .min();
+    if let Some(own) = own {
+        return own.clone();
+    }
+
+    // Don't steal the name of a label that points somewhere else.
+    let mut name = format!("L{}", pc);
+    while labels.contains_key(&name) {
+        name.push('_');
+    }
+    name
+}
+
+/// Returns a copy of `instruction` that refers to its target by name.
+pub(crate) fn named(instruction: &Instruction, names: &BTreeMap<usize, String>) -> Instruction {
+    let mut instruction = instruction.clone();
+    if let Some(label) = instruction.target_mut() {
+        if let Label::Instruction(pc) = label {
+            *label = Label::Label(names[pc].clone());
+        }
+    }
+    instruction
+}
@@ -0,0 +1,110 @@
+use crate::replay::Event;
+
+/// An undo log of executed instructions, see `RunnerBuilder::history`.
+///
+/// Each entry keeps the pc, the old value of every register the instruction
+/// wrote and how it changed the call stack, which is enough to put the runner
+/// back into the state before the instruction. Values read from the outside
+/// world are kept too, so executing the instruction again reads the same.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    /// Overwritten registers and their old values, for all entries back to back.
+    writes: Vec<(usize, i64)>,
+    /// Values read by `IN`, `CIN`, `TIME` and `SYS`, for all entries back to back.
+    events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    pc: usize,
+    /// Index of the first write of this entry in `History::writes`.
+    writes: usize,
+    /// Index of the first event of this entry in `History::events`.
+    events: usize,
+    stack: StackChange,
+}
+
+/// How an instruction changed the call stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackChange {
+    None,
+    /// A return address was pushed by `call`.
+    Pushed,
+    /// `ret` popped this return address.
+    Popped(usize),
+}
+
+/// An executed instruction, as seen from the undo log.
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryStep<'a> {
+    pub pc: usize,
+    /// Registers written by the instruction and the values they had before.
+    pub writes: &'a [(usize, i64)],
+    pub stack: StackChange,
+}
+
+impl History {
+    /// Number of instructions executed since the runner was reset.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The most recently executed instruction.
+    pub fn last(&self) -> Option<HistoryStep<'_>> {
+        let entry = self.entries.last()?;
+        Some(HistoryStep {
+            pc: entry.pc,
+            writes: &self.writes[entry.writes..],
+            stack: entry.stack,
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.writes.clear();
+        self.events.clear();
+    }
+
+    pub(crate) fn begin(&mut self, pc: usize) {
+        self.entries.push(Entry {
+            pc,
+            writes: self.writes.len(),
+            events: self.events.len(),
+            stack: StackChange::None,
+        });
+    }
+
+    pub(crate) fn write(&mut self, register: usize, old: i64) {
+        self.writes.push((register, old));
+    }
+
+    pub(crate) fn event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    pub(crate) fn set_stack_change(&mut self, change: StackChange) {
+        if let Some(entry) = self.entries.last_mut() {
+            entry.stack = change;
+        }
+    }
+
+    /// Removes the last entry, calling `undo` with each write and `redo` with
+    /// each event, newest first.
+    pub(crate) fn pop(
+        &mut self,
+        mut undo: impl FnMut(usize, i64),
+        redo: impl FnMut(Event),
+    ) -> Option<(usize, StackChange)> {
+        let entry = self.entries.pop()?;
+        for (register, old) in self.writes.drain(entry.writes..).rev() {
+            undo(register, old);
+        }
+        self.events.drain(entry.events..).rev().for_each(redo);
+        Some((entry.pc, entry.stack))
+    }
+}
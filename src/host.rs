@@ -0,0 +1,27 @@
+use std::fmt;
+
+/// A native function callable from YAUL with `call host:<name>`.
+///
+/// Host functions get the whole register file, reading their arguments from and
+/// writing their results to registers just like YAUL functions do.
+pub type HostFunction = Box<dyn FnMut(&mut [i64]) -> Result<(), Trap> + Send>;
+
+/// Stops the program from inside a host function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trap {
+    pub message: String,
+}
+
+impl Trap {
+    pub fn new(message: impl Into<String>) -> Trap {
+        Trap {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     Return,
     Set(Source, Destination),
@@ -16,6 +18,8 @@ pub enum Instruction {
     JumpEqual(Source, Source, Label),
     JumpLessThan(Source, Source, Label),
     Call(Label),
+    /// Calls a function registered by the host, `call host:<name>`.
+    HostCall(String),
     Time(Destination),
     Fault(String),
     Syscall(
@@ -30,21 +34,157 @@ pub enum Instruction {
     ),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Source {
     Address(usize),
     Reference(usize),
     Data(i64),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Destination {
     Address(usize),
     Reference(usize),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Label {
     Label(String),
     Instruction(usize),
 }
+
+impl Instruction {
+    /// Every mnemonic returned by `opcode`.
+    pub const OPCODES: &[&str] = &[
+        "RET", "SET", "IN", "CIN", "OUT", "COUT", "ADD", "SUB", "MUL", "DIV", "MOD", "JMP", "JGT",
+        "JEQ", "JLT", "CALL", "TIME", "FAULT", "SYS",
+    ];
+
+    /// The mnemonic the instruction is written with, in upper case.
+    pub fn opcode(&self) -> &'static str {
+        match self {
+            Instruction::Return => "RET",
+            Instruction::Set(..) => "SET",
+            Instruction::Input(..) => "IN",
+            Instruction::CharInput(..) => "CIN",
+            Instruction::Output(..) => "OUT",
+            Instruction::CharOutput(..) => "COUT",
+            Instruction::Add(..) => "ADD",
+            Instruction::Subtract(..) => "SUB",
+            Instruction::Multiply(..) => "MUL",
+            Instruction::Divide(..) => "DIV",
+            Instruction::Modulo(..) => "MOD",
+            Instruction::Jump(..) => "JMP",
+            Instruction::JumpGreaterThan(..) => "JGT",
+            Instruction::JumpEqual(..) => "JEQ",
+            Instruction::JumpLessThan(..) => "JLT",
+            Instruction::Call(..) | Instruction::HostCall(..) => "CALL",
+            Instruction::Time(..) => "TIME",
+            Instruction::Fault(..) => "FAULT",
+            Instruction::Syscall(..) => "SYS",
+        }
+    }
+
+    /// Where the instruction jumps or calls to, if it does.
+    pub fn target(&self) -> Option<&Label> {
+        match self {
+            Instruction::Jump(label)
+            | Instruction::JumpGreaterThan(_, _, label)
+            | Instruction::JumpEqual(_, _, label)
+            | Instruction::JumpLessThan(_, _, label)
+            | Instruction::Call(label) => Some(label),
+            _ => None,
+        }
+    }
+
+    pub fn target_mut(&mut self) -> Option<&mut Label> {
+        match self {
+            Instruction::Jump(label)
+            | Instruction::JumpGreaterThan(_, _, label)
+            | Instruction::JumpEqual(_, _, label)
+            | Instruction::JumpLessThan(_, _, label)
+            | Instruction::Call(label) => Some(label),
+            _ => None,
+        }
+    }
+}
+
+/// Prints the instruction in YAUL syntax, e.g. `add 0 '1' &2`. Resolved jump
+/// targets are printed as `L<index>`, see `disasm` for labels that parse again.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = self.opcode().to_lowercase();
+        match self {
+            Instruction::Return => write!(f, "{}", mnemonic),
+            Instruction::Set(a, destination) => write!(f, "{} {} {}", mnemonic, a, destination),
+            Instruction::Input(destination) | Instruction::Time(destination) => {
+                write!(f, "{} {}", mnemonic, destination)
+            }
+            Instruction::CharInput(destination, size) => {
+                write!(f, "{} {} {}", mnemonic, destination, size)
+            }
+            Instruction::Output(a) | Instruction::CharOutput(a) => write!(f, "{} {}", mnemonic, a),
+            Instruction::Add(a, b, destination)
+            | Instruction::Subtract(a, b, destination)
+            | Instruction::Multiply(a, b, destination)
+            | Instruction::Divide(a, b, destination)
+            | Instruction::Modulo(a, b, destination) => {
+                write!(f, "{} {} {} {}", mnemonic, a, b, destination)
+            }
+            Instruction::Jump(label) | Instruction::Call(label) => {
+                write!(f, "{} {}", mnemonic, label)
+            }
+            Instruction::JumpGreaterThan(a, b, label)
+            | Instruction::JumpEqual(a, b, label)
+            | Instruction::JumpLessThan(a, b, label) => {
+                write!(f, "{} {} {} {}", mnemonic, a, b, label)
+            }
+            Instruction::HostCall(name) => write!(f, "{} host:{}", mnemonic, name),
+            Instruction::Fault(message) => write!(f, "{} \"{}\"", mnemonic, message),
+            Instruction::Syscall(destination, number, a1, a2, a3, a4, a5, a6) => {
+                write!(f, "{} {} {}", mnemonic, destination, number)?;
+                // Missing arguments before a present one are written as `_`.
+                let args = [a1, a2, a3, a4, a5, a6];
+                let count = args
+                    .iter()
+                    .rposition(|arg| arg.is_some())
+                    .map_or(0, |i| i + 1);
+                for arg in &args[..count] {
+                    match arg {
+                        Some(arg) => write!(f, " {}", arg)?,
+                        None => write!(f, " _")?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Address(i) => write!(f, "{}", i),
+            Source::Reference(i) => write!(f, "&{}", i),
+            Source::Data(value) => write!(f, "'{}'", value),
+        }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Destination::Address(i) => write!(f, "{}", i),
+            Destination::Reference(i) => write!(f, "&{}", i),
+        }
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Label::Label(name) => write!(f, "{}", name),
+            Label::Instruction(pc) => write!(f, "L{}", pc),
+        }
+    }
+}
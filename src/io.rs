@@ -0,0 +1,71 @@
+use std::io::{self, BufRead, Cursor, Read, Write};
+
+/// Where a running program reads input from and writes output to.
+pub trait Io {
+    /// Reads a line for `IN`, including the line terminator. Returns 0 at the end of input.
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize>;
+
+    /// Reads up to `buf.len()` bytes for `CIN`.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Writes the output of `OUT` and `COUT`.
+    fn write(&mut self, buf: &[u8]) -> io::Result<()>;
+}
+
+/// Reads from stdin and writes to stdout.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdIo;
+
+impl Io for StdIo {
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        io::stdin().read_line(buf)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::stdin().lock().read(buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        io::stdout().lock().write_all(buf)
+    }
+}
+
+/// Reads from a fixed input and collects all output in memory.
+#[derive(Debug, Default, Clone)]
+pub struct BufferIo {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl BufferIo {
+    pub fn new(input: impl Into<Vec<u8>>) -> BufferIo {
+        BufferIo {
+            input: Cursor::new(input.into()),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// The output decoded as UTF-8, with invalid sequences replaced.
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+}
+
+impl Io for BufferIo {
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.input.read_line(buf)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.output.extend_from_slice(buf);
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+//! YAUL - Yet Another Useless Language
+//!
+//! Parse a program with [`Program::from_file`] or [`Program::from_source`] and
+//! execute it with a [`Runner`]:
+//!
+//! ```
+//! use yaul::{Program, Runner};
+//!
+//! let program = Program::from_source("set '42' 0").unwrap();
+//! let mut runner = Runner::builder().registers(16).build();
+//! runner.run(&program).unwrap();
+//!
+//! assert_eq!(runner.registers()[0], 42);
+//! ```
+
+mod bounds;
+pub mod bytecode;
+pub mod cfg;
+pub mod check;
+pub mod coverage;
+pub mod debugger;
+pub mod disasm;
+pub mod history;
+pub mod host;
+pub mod instructions;
+pub mod io;
+mod lower;
+pub mod observer;
+pub mod optimize;
+pub mod parser;
+pub mod profile;
+pub mod program;
+mod range;
+pub mod replay;
+pub mod runner;
+pub mod snapshot;
+pub mod source;
+mod syscall;
+pub mod trace;
+pub mod watch;
+
+pub use bytecode::BytecodeError;
+pub use cfg::{BasicBlock, Cfg, Loop};
+pub use check::{check, Lint, LintKind};
+pub use coverage::{BranchCoverage, Coverage, LineCoverage};
+pub use debugger::{Debugger, Stop};
+pub use disasm::disassemble;
+pub use history::{History, HistoryStep, StackChange};
+pub use host::{HostFunction, Trap};
+pub use io::{BufferIo, Io, StdIo};
+pub use observer::{Observer, Outcome, State};
+pub use optimize::{optimize, Pass, Passes};
+pub use parser::{Diagnostic, ParseError, Parser, Severity};
+pub use profile::{FunctionProfile, Profiler};
+pub use program::Program;
+pub use replay::{Event, Recording};
+pub use runner::{ExitStatus, Runner, RunnerBuilder, RuntimeError, RuntimeErrorKind};
+pub use snapshot::{Snapshot, SnapshotError};
+pub use syscall::SyscallError;
+pub use trace::{PcRange, TraceFilter, Tracer};
+pub use watch::{Access, WatchHit, Watchpoint};
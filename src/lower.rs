@@ -0,0 +1,109 @@
+use crate::instructions::{Destination, Instruction, Label, Source};
+
+/// An instruction with its operand modes and jump target decided ahead of
+/// time, so the runner doesn't have to match on `Source`, `Destination` and
+/// `Label` for every step. `Reg` operands are register addresses and `Imm`
+/// operands values, in the order the instruction takes them.
+///
+/// Only the common forms are specialized. Everything else, including any
+/// operand that goes through a reference, is `Other` and runs from the parsed
+/// instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Op {
+    Return,
+    SetImm(i64, usize),
+    SetReg(usize, usize),
+    AddRegReg(usize, usize, usize),
+    AddRegImm(usize, i64, usize),
+    AddImmReg(i64, usize, usize),
+    SubRegReg(usize, usize, usize),
+    SubRegImm(usize, i64, usize),
+    SubImmReg(i64, usize, usize),
+    MulRegReg(usize, usize, usize),
+    MulRegImm(usize, i64, usize),
+    MulImmReg(i64, usize, usize),
+    DivRegReg(usize, usize, usize),
+    /// The divisor is never 0, that stays `Other` so it fails at runtime.
+    DivRegImm(usize, i64, usize),
+    DivImmReg(i64, usize, usize),
+    ModRegReg(usize, usize, usize),
+    /// The divisor is never 0, that stays `Other` so it fails at runtime.
+    ModRegImm(usize, i64, usize),
+    ModImmReg(i64, usize, usize),
+    Jump(usize),
+    JgtRegReg(usize, usize, usize),
+    JgtRegImm(usize, i64, usize),
+    JgtImmReg(i64, usize, usize),
+    JeqRegReg(usize, usize, usize),
+    JeqRegImm(usize, i64, usize),
+    JeqImmReg(i64, usize, usize),
+    JltRegReg(usize, usize, usize),
+    JltRegImm(usize, i64, usize),
+    JltImmReg(i64, usize, usize),
+    Call(usize),
+    Other,
+}
+
+/// Lowers every instruction, keeping their indices.
+pub(crate) fn lower(instructions: &[Instruction]) -> Vec<Op> {
+    instructions.iter().map(lower_instruction).collect()
+}
+
+fn lower_instruction(instruction: &Instruction) -> Op {
+    match instruction {
+        Instruction::Return => Op::Return,
+        Instruction::Set(value, Destination::Address(d)) => match value {
+            Source::Data(value) => Op::SetImm(*value, *d),
+            Source::Address(a) => Op::SetReg(*a, *d),
+            Source::Reference(_) => Op::Other,
+        },
+        Instruction::Add(a, b, Destination::Address(d)) => {
+            binary(a, b, *d, Op::AddRegReg, Op::AddRegImm, Op::AddImmReg)
+        }
+        Instruction::Subtract(a, b, Destination::Address(d)) => {
+            binary(a, b, *d, Op::SubRegReg, Op::SubRegImm, Op::SubImmReg)
+        }
+        Instruction::Multiply(a, b, Destination::Address(d)) => {
+            binary(a, b, *d, Op::MulRegReg, Op::MulRegImm, Op::MulImmReg)
+        }
+        Instruction::Divide(_, Source::Data(0), _) | Instruction::Modulo(_, Source::Data(0), _) => {
+            Op::Other
+        }
+        Instruction::Divide(a, b, Destination::Address(d)) => {
+            binary(a, b, *d, Op::DivRegReg, Op::DivRegImm, Op::DivImmReg)
+        }
+        Instruction::Modulo(a, b, Destination::Address(d)) => {
+            binary(a, b, *d, Op::ModRegReg, Op::ModRegImm, Op::ModImmReg)
+        }
+        Instruction::Jump(Label::Instruction(target)) => Op::Jump(*target),
+        Instruction::JumpGreaterThan(a, b, Label::Instruction(target)) => {
+            binary(a, b, *target, Op::JgtRegReg, Op::JgtRegImm, Op::JgtImmReg)
+        }
+        Instruction::JumpEqual(a, b, Label::Instruction(target)) => {
+            binary(a, b, *target, Op::JeqRegReg, Op::JeqRegImm, Op::JeqImmReg)
+        }
+        Instruction::JumpLessThan(a, b, Label::Instruction(target)) => {
+            binary(a, b, *target, Op::JltRegReg, Op::JltRegImm, Op::JltImmReg)
+        }
+        Instruction::Call(Label::Instruction(target)) => Op::Call(*target),
+        _ => Op::Other,
+    }
+}
+
+/// Picks the variant for two direct operands and a third plain operand, the
+/// destination or jump target.
+fn binary(
+    a: &Source,
+    b: &Source,
+    c: usize,
+    reg_reg: fn(usize, usize, usize) -> Op,
+    reg_imm: fn(usize, i64, usize) -> Op,
+    imm_reg: fn(i64, usize, usize) -> Op,
+) -> Op {
+    match (a, b) {
+        (Source::Address(a), Source::Address(b)) => reg_reg(*a, *b, c),
+        (Source::Address(a), Source::Data(b)) => reg_imm(*a, *b, c),
+        (Source::Data(a), Source::Address(b)) => imm_reg(*a, *b, c),
+        _ => Op::Other,
+    }
+}
@@ -1,31 +1,320 @@
 mod args;
-mod instructions;
-mod parser;
-mod runner;
-mod syscall;
+mod debug;
 
-use args::Args;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use args::{Args, CfgArgs, CheckArgs, Command, CompileArgs, RunArgs};
 use clap::Parser;
+use yaul::{
+    bytecode, Cfg, Coverage, ExitStatus, Observer, Pass, Passes, Profiler, Program, Recording,
+    Runner, RunnerBuilder, RuntimeError, Severity, StdIo, TraceFilter, Tracer,
+};
+
+/// How many of the most executed instructions `--profile` lists.
+const PROFILE_INSTRUCTIONS: usize = 20;
 
 fn main() {
     let args = Args::parse();
 
-    let mut parser = parser::Parser::new(args.file);
+    match args.command {
+        Some(Command::Run(args)) => run_file(&args),
+        Some(Command::Debug(args)) => debug::debug(&args),
+        Some(Command::Compile(args)) => compile_file(&args),
+        Some(Command::Cfg(args)) => write_cfg(&args),
+        Some(Command::Check(args)) => check_file(&args),
+        Some(Command::Disasm(args)) => {
+            let program = optimize(read_program(&args.file).0, args.optimize, &args.skip_pass);
+            print!("{}", yaul::disassemble(&program));
+        }
+        None => run_file(&args.run.expect("file is required without a subcommand")),
+    }
+}
+
+/// Loads `file` with `read_program` and prints how long that took.
+fn load(file: &Path) -> (Program, bool) {
+    let start_time = std::time::Instant::now();
+    let (program, bytecode) = read_program(file);
+    if bytecode {
+        println!("Loaded in {:?}", start_time.elapsed());
+    } else {
+        println!("Compiled in {:?}", start_time.elapsed());
+    }
+    (program, bytecode)
+}
+
+/// Reads `file`, either as bytecode written by `yaul compile` or by parsing it
+/// and printing any diagnostics. Also returns whether it was bytecode. Exits the
+/// process if that fails.
+fn read_program(file: &Path) -> (Program, bool) {
+    let bytes = match std::fs::read(file) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", file.display(), e);
+            std::process::exit(1);
+        }
+    };
+    if !bytecode::is_bytecode(&bytes) {
+        return (parse(file), false);
+    }
+
+    match Program::from_bytecode(&bytes, file) {
+        Ok(program) => (program, true),
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", file.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    let compile_start_time = std::time::Instant::now();
+/// Parses `file`, printing any diagnostics. Exits the process if the program has errors.
+fn parse(file: &Path) -> Program {
+    let mut parser = yaul::Parser::new(file.to_path_buf());
+
+    let result = parser.parse();
+    for diagnostic in parser.diagnostics() {
+        eprintln!("{}\n", diagnostic);
+    }
+
+    match result {
+        Ok(program) => program,
+        Err(_) => {
+            let errors = parser
+                .diagnostics()
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .count();
+            eprintln!("Failed to parse instructions: {} error(s)", errors);
+            std::process::exit(1);
+        }
+    }
+}
 
-    let instructions = match parser.get_instructions() {
-        Ok(instructions) => instructions,
-        Err(e) => panic!("Failed to parse instructions: {}", e),
+fn compile_file(args: &CompileArgs) {
+    let program = parse(&args.file);
+    let bytes = match program.to_bytecode() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to compile {}: {}", args.file.display(), e);
+            std::process::exit(1);
+        }
     };
 
-    let compile_time = compile_start_time.elapsed();
-    println!("Compiled in {:?}", compile_time);
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.file.with_extension("yaulc"));
+    write_file(&output, |writer| writer.write_all(&bytes));
+    println!("Wrote {}", output.display());
+}
+
+fn write_cfg(args: &CfgArgs) {
+    let program = read_program(&args.file).0;
+    let cfg = Cfg::new(&program);
+    match &args.output {
+        Some(path) => write_file(path, |writer| cfg.write_dot(&program, writer)),
+        None => {
+            if let Err(e) = cfg.write_dot(&program, io::stdout().lock()) {
+                eprintln!("Failed to write the graph: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Prints the lints found in the program. Exits with 1 if there are any.
+fn check_file(args: &CheckArgs) {
+    let program = read_program(&args.file).0;
+    let lints = yaul::check(&program);
+    for lint in &lints {
+        match lint.span(program.source_map()) {
+            Some(span) => println!(
+                "warning: {}\n{}\n",
+                lint.kind,
+                program.source_map().excerpt(span)
+            ),
+            None => println!("warning: {} at instruction {}\n", lint.kind, lint.pc),
+        }
+    }
+
+    if lints.is_empty() {
+        println!("No problems found");
+    } else {
+        println!("{} warning(s)", lints.len());
+        std::process::exit(1);
+    }
+}
+
+/// Runs the optimization passes asked for with `-O`, if any.
+fn optimize(program: Program, optimize: bool, skip: &[Pass]) -> Program {
+    if !optimize {
+        return program;
+    }
+
+    let mut passes = Passes::default();
+    for pass in skip {
+        passes.set(*pass, false);
+    }
+    yaul::optimize(&program, passes)
+}
+
+fn run_file(args: &RunArgs) {
+    let (program, bytecode) = load(&args.file);
+    if bytecode && args.lcov.is_some() {
+        eprintln!(
+            "Cannot write lcov for {}: bytecode has no source lines",
+            args.file.display()
+        );
+        std::process::exit(1);
+    }
+    let program = optimize(program, args.optimize, &args.skip_pass);
+
+    let mut builder = Runner::builder().registers(args.registers as usize);
+    for watchpoint in &args.watch {
+        builder = builder.watch(*watchpoint);
+    }
+    if let Some(path) = &args.record {
+        builder = builder.record_to(recording_log(path));
+    }
+    if let Some(path) = &args.replay {
+        builder = builder.replay(read_recording(path));
+    }
+    // Safety: the user opted out of bounds checks with --fast.
+    let builder = unsafe { builder.fast(args.fast) };
 
-    let mut runner = runner::Runner::new(args.registers as usize);
-    if args.fast {
-        runner.run::<true>(&instructions);
+    let profiler = (args.profile || args.profile_folded.is_some()).then(|| Profiler::new(&program));
+    let coverage = (args.coverage || args.lcov.is_some()).then(|| Coverage::new(&program));
+    let tracer = tracer(args);
+    let result = if profiler.is_none() && coverage.is_none() && tracer.is_none() {
+        run(builder, &program).0
     } else {
-        runner.run::<false>(&instructions);
+        let (result, ((profiler, coverage), tracer)) =
+            run(builder.observer(((profiler, coverage), tracer)), &program);
+        // Flush the trace before reporting.
+        if let (Some(tracer), Some(path)) = (tracer, &args.logs) {
+            finish_trace(path, tracer);
+        }
+        if let Some(profiler) = profiler {
+            report_profile(args, &profiler);
+        }
+        if let Some(coverage) = coverage {
+            report_coverage(args, &coverage);
+        }
+        result
+    };
+
+    if let Err(e) = result {
+        eprintln!("Runtime error: {}", e);
+        if let Some(span) = program.source_map().instruction(e.pc) {
+            eprintln!("{}", program.source_map().excerpt(span.span));
+        }
+        std::process::exit(1);
+    }
+}
+
+fn tracer(args: &RunArgs) -> Option<Tracer<BufWriter<File>>> {
+    let path = args.logs.as_ref()?;
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Failed to create {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let filter = TraceFilter {
+        pc_ranges: args.logs_pc.clone(),
+        opcodes: args.logs_op.clone(),
+    };
+    Some(Tracer::new(BufWriter::new(file), filter))
+}
+
+/// Flushes the trace to `path`, exiting the process if any of it couldn't be written.
+fn finish_trace(path: &Path, tracer: Tracer<BufWriter<File>>) {
+    if let Err(e) = tracer.finish() {
+        eprintln!("Failed to write {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+fn report_profile(args: &RunArgs, profiler: &Profiler) {
+    if args.profile {
+        eprintln!("\n{}", profiler.report(PROFILE_INSTRUCTIONS));
+    }
+
+    if let Some(path) = &args.profile_folded {
+        write_file(path, |writer| profiler.write_folded(writer));
+    }
+}
+
+fn report_coverage(args: &RunArgs, coverage: &Coverage) {
+    if args.coverage {
+        eprintln!("\n{}", coverage.summary());
+    }
+
+    if let Some(path) = &args.lcov {
+        write_file(path, |writer| coverage.write_lcov(writer));
+    }
+}
+
+/// Creates `path` and writes it with `write`, exiting the process on failure.
+fn write_file(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) {
+    let result = File::create(path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()
+    });
+    if let Err(e) = result {
+        eprintln!("Failed to write {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+/// Runs the program to completion, reporting watchpoint hits along the way.
+/// Hands back the observer so it can be inspected or dropped to flush its output.
+fn run<O: Observer>(
+    builder: RunnerBuilder<StdIo, O>,
+    program: &Program,
+) -> (Result<ExitStatus, RuntimeError>, O) {
+    let mut runner = builder.build();
+    let mut result = runner.run(program);
+    while let Ok(ExitStatus::Watchpoint(hit)) = result {
+        eprintln!("Watchpoint: {} by instruction {}", hit, hit.pc);
+        if let Some(span) = program.source_map().instruction(hit.pc) {
+            eprintln!("{}\n", program.source_map().excerpt(span.span));
+        }
+        result = runner.resume(program);
+    }
+
+    (result, runner.into_observer())
+}
+
+/// Creates `path` with the header of a recording for the runner to add events
+/// to as they happen. Exits the process on failure.
+fn recording_log(path: &Path) -> BufWriter<File> {
+    let result = File::create(path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write!(writer, "{}", Recording::default())?;
+        Ok(writer)
+    });
+    match result {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_recording(path: &Path) -> Recording {
+    let recording = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| contents.parse::<Recording>());
+    match recording {
+        Ok(recording) => recording,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
     }
 }
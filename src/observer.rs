@@ -0,0 +1,173 @@
+use crate::instructions::{Instruction, Source};
+
+/// The state of the machine right before an instruction is executed.
+#[derive(Debug, Clone, Copy)]
+pub struct State<'a> {
+    pub registers: &'a [i64],
+    /// Return addresses of the active calls, innermost last.
+    pub stack: &'a [usize],
+}
+
+impl State<'_> {
+    /// The value `source` reads, or `None` if it reads out of bounds.
+    pub fn value(&self, source: &Source) -> Option<i64> {
+        match source {
+            Source::Data(value) => Some(*value),
+            Source::Address(i) => self.registers.get(*i).copied(),
+            Source::Reference(i) => {
+                let i = *self.registers.get(*i)?;
+                self.registers.get(i as usize).copied()
+            }
+        }
+    }
+}
+
+/// What happened after an instruction was executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Execution continues at the given instruction.
+    Continue(usize),
+    /// The program returned from main.
+    Exit,
+    /// The instruction failed with a runtime error.
+    Failed,
+}
+
+/// Hooks called by the `Runner` as it executes a program.
+///
+/// Every method does nothing by default, so implementations only override what
+/// they need. The `()` observer is used when nothing is attached and compiles
+/// down to no overhead.
+pub trait Observer {
+    fn before_instruction(&mut self, _pc: usize, _instruction: &Instruction, _state: State) {}
+
+    fn register_read(&mut self, _register: usize, _value: i64) {}
+
+    fn register_written(&mut self, _register: usize, _old: i64, _new: i64) {}
+
+    fn after_instruction(&mut self, _pc: usize, _outcome: Outcome) {}
+
+    /// Whether `register_written` should also be called for registers changed
+    /// by host functions. Finding those means copying and comparing every
+    /// register around each host call, so observers that ignore writes opt out.
+    fn observes_writes(&self) -> bool {
+        true
+    }
+}
+
+impl Observer for () {
+    fn observes_writes(&self) -> bool {
+        false
+    }
+}
+
+impl<O: Observer + ?Sized> Observer for Box<O> {
+    fn before_instruction(&mut self, pc: usize, instruction: &Instruction, state: State) {
+        (**self).before_instruction(pc, instruction, state)
+    }
+
+    fn register_read(&mut self, register: usize, value: i64) {
+        (**self).register_read(register, value)
+    }
+
+    fn register_written(&mut self, register: usize, old: i64, new: i64) {
+        (**self).register_written(register, old, new)
+    }
+
+    fn after_instruction(&mut self, pc: usize, outcome: Outcome) {
+        (**self).after_instruction(pc, outcome)
+    }
+
+    fn observes_writes(&self) -> bool {
+        (**self).observes_writes()
+    }
+}
+
+/// Forwards every hook to each observer in turn.
+impl<O: Observer> Observer for Vec<O> {
+    fn before_instruction(&mut self, pc: usize, instruction: &Instruction, state: State) {
+        for observer in self.iter_mut() {
+            observer.before_instruction(pc, instruction, state);
+        }
+    }
+
+    fn register_read(&mut self, register: usize, value: i64) {
+        for observer in self.iter_mut() {
+            observer.register_read(register, value);
+        }
+    }
+
+    fn register_written(&mut self, register: usize, old: i64, new: i64) {
+        for observer in self.iter_mut() {
+            observer.register_written(register, old, new);
+        }
+    }
+
+    fn after_instruction(&mut self, pc: usize, outcome: Outcome) {
+        for observer in self.iter_mut() {
+            observer.after_instruction(pc, outcome);
+        }
+    }
+
+    fn observes_writes(&self) -> bool {
+        self.iter().any(Observer::observes_writes)
+    }
+}
+
+/// Forwards every hook to the observer if there is one.
+impl<O: Observer> Observer for Option<O> {
+    fn before_instruction(&mut self, pc: usize, instruction: &Instruction, state: State) {
+        if let Some(observer) = self {
+            observer.before_instruction(pc, instruction, state);
+        }
+    }
+
+    fn register_read(&mut self, register: usize, value: i64) {
+        if let Some(observer) = self {
+            observer.register_read(register, value);
+        }
+    }
+
+    fn register_written(&mut self, register: usize, old: i64, new: i64) {
+        if let Some(observer) = self {
+            observer.register_written(register, old, new);
+        }
+    }
+
+    fn after_instruction(&mut self, pc: usize, outcome: Outcome) {
+        if let Some(observer) = self {
+            observer.after_instruction(pc, outcome);
+        }
+    }
+
+    fn observes_writes(&self) -> bool {
+        self.as_ref().is_some_and(Observer::observes_writes)
+    }
+}
+
+/// Forwards every hook to both observers, first `A` then `B`.
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    fn before_instruction(&mut self, pc: usize, instruction: &Instruction, state: State) {
+        self.0.before_instruction(pc, instruction, state);
+        self.1.before_instruction(pc, instruction, state);
+    }
+
+    fn register_read(&mut self, register: usize, value: i64) {
+        self.0.register_read(register, value);
+        self.1.register_read(register, value);
+    }
+
+    fn register_written(&mut self, register: usize, old: i64, new: i64) {
+        self.0.register_written(register, old, new);
+        self.1.register_written(register, old, new);
+    }
+
+    fn after_instruction(&mut self, pc: usize, outcome: Outcome) {
+        self.0.after_instruction(pc, outcome);
+        self.1.after_instruction(pc, outcome);
+    }
+
+    fn observes_writes(&self) -> bool {
+        self.0.observes_writes() || self.1.observes_writes()
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::instructions::{Destination, Instruction, Label, Source};
+use crate::program::Program;
+
+/// Which passes `optimize` runs. All of them by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Passes {
+    pub jump_threading: bool,
+    pub dead_stores: bool,
+    pub constant_folding: bool,
+}
+
+/// A single pass of `optimize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Points jumps and calls at the end of a chain of `jmp`s and removes
+    /// `jmp`s to the next instruction.
+    JumpThreading,
+    /// Removes writes to a register that is overwritten before anything reads it.
+    DeadStores,
+    /// Evaluates arithmetic and conditional jumps whose operands are all values.
+    ConstantFolding,
+}
+
+impl Default for Passes {
+    fn default() -> Self {
+        Passes {
+            jump_threading: true,
+            dead_stores: true,
+            constant_folding: true,
+        }
+    }
+}
+
+impl Passes {
+    pub fn none() -> Passes {
+        Passes {
+            jump_threading: false,
+            dead_stores: false,
+            constant_folding: false,
+        }
+    }
+
+    pub fn set(&mut self, pass: Pass, enabled: bool) {
+        match pass {
+            Pass::JumpThreading => self.jump_threading = enabled,
+            Pass::DeadStores => self.dead_stores = enabled,
+            Pass::ConstantFolding => self.constant_folding = enabled,
+        }
+    }
+}
+
+/// Returns an optimized copy of `program` that produces the same output.
+///
+/// The passes run until none of them finds anything more to change. Removed
+/// instructions take their source locations with them and labels move to the
+/// next instruction that is kept. Programs that fail may fail differently, for
+/// example when a removed store was out of bounds.
+pub fn optimize(program: &Program, passes: Passes) -> Program {
+    let mut instructions = program.instructions().to_vec();
+    let mut labels = program.labels().clone();
+    let mut source_map = program.source_map().clone();
+
+    loop {
+        let mut keep = vec![true; instructions.len()];
+        let mut changed = false;
+        if passes.constant_folding {
+            changed |= fold_constants(&mut instructions, &mut keep);
+        }
+        if passes.jump_threading {
+            changed |= thread_jumps(&mut instructions, &mut keep);
+        }
+        if passes.dead_stores {
+            remove_dead_stores(&instructions, &mut keep);
+        }
+        if !changed && keep.iter().all(|keep| *keep) {
+            break;
+        }
+
+        // Anything that pointed at a removed instruction now points at the next
+        // one that is kept, which is where execution would have ended up.
+        let mut new_index = Vec::with_capacity(keep.len() + 1);
+        let mut kept = 0;
+        for keep in &keep {
+            new_index.push(kept);
+            kept += usize::from(*keep);
+        }
+        new_index.push(kept);
+
+        let mut pc = 0;
+        instructions.retain_mut(|instruction| {
+            pc += 1;
+            if let Some(Label::Instruction(target)) = instruction.target_mut() {
+                if let Some(index) = new_index.get(*target) {
+                    *target = *index;
+                }
+            }
+            keep[pc - 1]
+        });
+        for target in labels.values_mut() {
+            if let Some(index) = new_index.get(*target) {
+                *target = *index;
+            }
+        }
+        source_map.retain_instructions(&keep);
+    }
+
+    Program::new(instructions, labels, source_map)
+}
+
+fn fold_constants(instructions: &mut [Instruction], keep: &mut [bool]) -> bool {
+    let mut changed = false;
+    for (pc, instruction) in instructions.iter_mut().enumerate() {
+        let folded = match &*instruction {
+            Instruction::Add(Source::Data(a), Source::Data(b), destination) => {
+                Instruction::Set(Source::Data(a.wrapping_add(*b)), destination.clone())
+            }
+            Instruction::Subtract(Source::Data(a), Source::Data(b), destination) => {
+                Instruction::Set(Source::Data(a.wrapping_sub(*b)), destination.clone())
+            }
+            Instruction::Multiply(Source::Data(a), Source::Data(b), destination) => {
+                Instruction::Set(Source::Data(a.wrapping_mul(*b)), destination.clone())
+            }
+            // Division by zero is left to fail at runtime.
+            Instruction::Divide(Source::Data(a), Source::Data(b), destination) if *b != 0 => {
+                Instruction::Set(Source::Data(a.wrapping_div(*b)), destination.clone())
+            }
+            Instruction::Modulo(Source::Data(a), Source::Data(b), destination) if *b != 0 => {
+                Instruction::Set(Source::Data(a.wrapping_rem(*b)), destination.clone())
+            }
+            Instruction::JumpGreaterThan(Source::Data(a), Source::Data(b), label)
+            | Instruction::JumpEqual(Source::Data(a), Source::Data(b), label)
+            | Instruction::JumpLessThan(Source::Data(a), Source::Data(b), label) => {
+                let jumps = match instruction {
+                    Instruction::JumpGreaterThan(..) => a > b,
+                    Instruction::JumpEqual(..) => a == b,
+                    _ => a < b,
+                };
+                if !jumps {
+                    keep[pc] = false;
+                    changed = true;
+                    continue;
+                }
+                Instruction::Jump(label.clone())
+            }
+            _ => continue,
+        };
+        *instruction = folded;
+        changed = true;
+    }
+    changed
+}
+
+fn thread_jumps(instructions: &mut [Instruction], keep: &mut [bool]) -> bool {
+    let mut changed = false;
+    for pc in 0..instructions.len() {
+        let Some(Label::Instruction(start)) = instructions[pc].target() else {
+            continue;
+        };
+
+        // Follow the chain, giving up on jumps that go around in circles.
+        let mut end = *start;
+        let mut seen = HashSet::from([pc]);
+        while let Some(Instruction::Jump(Label::Instruction(next))) = instructions.get(end) {
+            if !seen.insert(end) {
+                end = *start;
+                break;
+            }
+            end = *next;
+        }
+
+        if end != *start {
+            if let Some(Label::Instruction(target)) = instructions[pc].target_mut() {
+                *target = end;
+            }
+            changed = true;
+        }
+        if matches!(instructions[pc], Instruction::Jump(_)) && end == pc + 1 && keep[pc] {
+            keep[pc] = false;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Marks stores that are overwritten later in the same straight line of code,
+/// with nothing reading the register in between.
+fn remove_dead_stores(instructions: &[Instruction], keep: &mut [bool]) {
+    // Instructions that can be reached other than from the one before them.
+    let mut entries = HashSet::new();
+    for (pc, instruction) in instructions.iter().enumerate() {
+        if let Some(Label::Instruction(target)) = instruction.target() {
+            entries.insert(*target);
+        }
+        if let Instruction::Call(_) = instruction {
+            entries.insert(pc + 1);
+        }
+    }
+
+    for pc in 0..instructions.len() {
+        if !keep[pc] {
+            continue;
+        }
+        let Some(register) = removable_store(&instructions[pc]) else {
+            continue;
+        };
+
+        for next in pc + 1..instructions.len() {
+            if entries.contains(&next) {
+                break;
+            }
+            if !keep[next] {
+                continue;
+            }
+            let Some(access) = straight_line_access(&instructions[next]) else {
+                break;
+            };
+            if access.reads.contains(&register) {
+                break;
+            }
+            if access.write == Some(register) {
+                keep[pc] = false;
+                break;
+            }
+        }
+    }
+}
+
+/// The register written by an instruction that has no effect other than the
+/// write, and can't fail other than by being out of bounds.
+fn removable_store(instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::Output(_) | Instruction::CharOutput(_) => return None,
+        // Removing a division by a register could hide a division by zero.
+        Instruction::Divide(_, divisor, _) | Instruction::Modulo(_, divisor, _)
+            if !divides_by_nonzero_constant(divisor) =>
+        {
+            return None
+        }
+        _ => {}
+    }
+    straight_line_access(instruction)?.write
+}
+
+fn divides_by_nonzero_constant(divisor: &Source) -> bool {
+    matches!(divisor, Source::Data(value) if *value != 0)
+}
+
+/// Registers read and written by an instruction that always continues with the
+/// next one, or fails. `None` for anything else, or if it uses a reference
+/// and could touch any register.
+struct Access {
+    reads: Vec<usize>,
+    write: Option<usize>,
+}
+
+fn straight_line_access(instruction: &Instruction) -> Option<Access> {
+    let (sources, destination) = match instruction {
+        Instruction::Set(a, d) => (vec![a], Some(d)),
+        Instruction::Output(a) | Instruction::CharOutput(a) => (vec![a], None),
+        Instruction::Add(a, b, d)
+        | Instruction::Subtract(a, b, d)
+        | Instruction::Multiply(a, b, d)
+        | Instruction::Divide(a, b, d)
+        | Instruction::Modulo(a, b, d) => (vec![a, b], Some(d)),
+        _ => return None,
+    };
+
+    let mut reads = Vec::new();
+    for source in sources {
+        match source {
+            Source::Address(i) => reads.push(*i),
+            Source::Reference(_) => return None,
+            Source::Data(_) => {}
+        }
+    }
+    let write = match destination {
+        Some(Destination::Address(i)) => Some(*i),
+        Some(Destination::Reference(_)) => return None,
+        None => None,
+    };
+    Some(Access { reads, write })
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pass::JumpThreading => write!(f, "jump-threading"),
+            Pass::DeadStores => write!(f, "dead-stores"),
+            Pass::ConstantFolding => write!(f, "constant-folding"),
+        }
+    }
+}
+
+impl FromStr for Pass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jump-threading" => Ok(Pass::JumpThreading),
+            "dead-stores" => Ok(Pass::DeadStores),
+            "constant-folding" => Ok(Pass::ConstantFolding),
+            _ => Err(format!(
+                "Unknown pass: {}, expected jump-threading, dead-stores or constant-folding",
+                s
+            )),
+        }
+    }
+}
@@ -1,142 +1,245 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Cursor};
 use std::path::PathBuf;
 
 use crate::instructions::{Destination, Instruction, Label, Source};
+use crate::program::Program;
+use crate::source::{render_excerpt, InstructionSpan, SourceMap, Span};
 
 pub struct Parser {
     file: PathBuf,
+    /// Program text to parse instead of reading `file`.
+    source: Option<String>,
     labels: HashMap<String, usize>,
+    label_spans: HashMap<String, Span>,
+    referenced_labels: HashSet<String>,
+    source_map: SourceMap,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A whitespace separated chunk of a line and where it was found.
+struct Token<'a> {
+    text: &'a str,
+    span: Span,
 }
 
 impl Parser {
     pub fn new(file: PathBuf) -> Parser {
         Parser {
+            source_map: SourceMap::new(file.clone()),
             file,
+            source: None,
             labels: HashMap::new(),
+            label_spans: HashMap::new(),
+            referenced_labels: HashSet::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Creates a parser for program text held in memory. `name` is only used to
+    /// refer to the source in diagnostics.
+    pub fn from_source(name: impl Into<PathBuf>, source: impl Into<String>) -> Parser {
+        Parser {
+            source: Some(source.into()),
+            ..Parser::new(name.into())
         }
     }
+
+    /// Parses and resolves the program, see `get_instructions`.
+    pub fn parse(&mut self) -> Result<Program, ParseError> {
+        let instructions = self.get_instructions()?;
+        Ok(Program::new(
+            instructions,
+            self.labels.clone(),
+            self.source_map.clone(),
+        ))
+    }
+
+    /// Source locations of the instructions returned by `get_instructions`.
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// Every error and warning found by `get_instructions`, sorted by line.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Parses the whole file, recovering from errors one line at a time.
+    /// Returns the first error if there were any, see `diagnostics` for all of them.
     pub fn get_instructions(&mut self) -> Result<Vec<Instruction>, ParseError> {
-        let file = match File::open(&self.file) {
-            Ok(file) => file,
-            Err(e) => {
-                return Err(ParseError::new(
-                    &format!("Failed to open file: {}", e),
-                    None,
-                ))
-            }
+        let reader: Box<dyn BufRead> = match &self.source {
+            Some(source) => Box::new(Cursor::new(source.clone())),
+            None => match File::open(&self.file) {
+                Ok(file) => Box::new(BufReader::new(file)),
+                Err(e) => {
+                    let error = ParseError::new(&format!("Failed to open file: {}", e), None);
+                    self.diagnostics.push(Diagnostic::error(error.clone()));
+                    return Err(error);
+                }
+            },
         };
 
-        let reader = BufReader::new(file);
-
         let mut instructions = Vec::new();
 
         for (line_idx, line_result) in reader.lines().enumerate() {
             let line = match line_result {
                 Ok(line) => line,
                 Err(e) => {
-                    return Err(ParseError::new(
+                    // The rest of the file can't be trusted after a read failure
+                    self.diagnostics.push(Diagnostic::error(ParseError::new(
                         &format!("Failed to read line: {}", e),
                         Some(ParseErrorLineDetails {
-                            line: line_idx,
+                            file: self.file.clone(),
+                            span: Span::new(line_idx + 1, 1, 0),
                             contents: None,
                         }),
-                    ))
+                    )));
+                    break;
                 }
             };
 
+            self.source_map.push_line(line.clone());
+
             if self.line_is_non_functional(&line) {
                 continue;
             }
             if self.line_is_label(&line) {
                 let label = line.split(":").collect::<Vec<&str>>()[0];
                 let instruction_id = instructions.len();
+                self.define_label(label, Span::new(line_idx + 1, 1, label.chars().count()));
                 self.labels.insert(label.to_string(), instruction_id);
                 continue;
             }
 
-            instructions.push(self.parse_instruction(&line)?);
+            match self.parse_instruction(&line, line_idx + 1) {
+                Ok((instruction, span)) => {
+                    instructions.push(instruction);
+                    self.source_map.push_instruction(span);
+                }
+                Err(e) => self.diagnostics.push(Diagnostic::error(e)),
+            }
         }
 
-        self.resolve_labels(&mut instructions)?;
+        self.resolve_labels(&mut instructions);
+        self.warn_unused_labels();
 
-        Ok(instructions)
+        self.diagnostics
+            .sort_by_key(|diagnostic| diagnostic.error.span().map(|span| (span.line, span.column)));
+
+        match self
+            .diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.severity == Severity::Error)
+        {
+            Some(diagnostic) => Err(diagnostic.error.clone()),
+            None => Ok(instructions),
+        }
     }
 
-    fn parse_instruction(&mut self, line: &str) -> Result<Instruction, ParseError> {
-        // TODO: Clean up
-        let sanitized_line = if let Some(index) = line.find("//") {
-            line[..index].trim()
-        } else {
-            line.trim()
-        };
+    fn define_label(&mut self, label: &str, span: Span) {
+        if let Some(previous) = self.label_spans.get(label) {
+            let warning = self.error_at(
+                &format!(
+                    "Label {} is already defined on line {}, this definition replaces it",
+                    label, previous.line
+                ),
+                span,
+            );
+            self.diagnostics.push(Diagnostic::warning(warning));
+        }
+        self.label_spans.insert(label.to_string(), span);
+    }
+
+    fn warn_unused_labels(&mut self) {
+        let mut unused = self
+            .label_spans
+            .iter()
+            .filter(|(label, _)| !self.referenced_labels.contains(*label))
+            .map(|(label, span)| self.error_at(&format!("Label {} is never used", label), *span))
+            .map(Diagnostic::warning)
+            .collect::<Vec<Diagnostic>>();
+        self.diagnostics.append(&mut unused);
+    }
+
+    fn parse_instruction(
+        &mut self,
+        line: &str,
+        line_number: usize,
+    ) -> Result<(Instruction, InstructionSpan), ParseError> {
+        let chunks = tokenize(line, line_number);
+        let instruction_id = chunks[0].text.to_uppercase();
 
-        let chunks = sanitized_line.split_whitespace().collect::<Vec<&str>>();
-        let instruction_id = chunks[0].to_uppercase();
+        if let Some(arity) = operand_count(&instruction_id) {
+            self.check_operand_count(&instruction_id, arity, &chunks)?;
+        }
 
         // Use a match expression for direct mapping
         let i = match instruction_id.as_str() {
             "RET" => Instruction::Return,
             "SET" => Instruction::Set(
-                self.parse_source(chunks[1])?,
-                self.parse_destination(chunks[2])?,
+                self.parse_source(&chunks[1])?,
+                self.parse_destination(&chunks[2])?,
             ),
-            "IN" => Instruction::Input(self.parse_destination(chunks[1])?),
+            "IN" => Instruction::Input(self.parse_destination(&chunks[1])?),
             "CIN" => Instruction::CharInput(
-                self.parse_destination(chunks[1])?,
-                self.parse_source(chunks[2])?,
+                self.parse_destination(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
             ),
-            "OUT" => Instruction::Output(self.parse_source(chunks[1])?),
-            "COUT" => Instruction::CharOutput(self.parse_source(chunks[1])?),
+            "OUT" => Instruction::Output(self.parse_source(&chunks[1])?),
+            "COUT" => Instruction::CharOutput(self.parse_source(&chunks[1])?),
             "ADD" => Instruction::Add(
-                self.parse_source(chunks[1])?,
-                self.parse_source(chunks[2])?,
-                self.parse_destination(chunks[3])?,
+                self.parse_source(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
+                self.parse_destination(&chunks[3])?,
             ),
             "SUB" => Instruction::Subtract(
-                self.parse_source(chunks[1])?,
-                self.parse_source(chunks[2])?,
-                self.parse_destination(chunks[3])?,
+                self.parse_source(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
+                self.parse_destination(&chunks[3])?,
             ),
             "MUL" => Instruction::Multiply(
-                self.parse_source(chunks[1])?,
-                self.parse_source(chunks[2])?,
-                self.parse_destination(chunks[3])?,
+                self.parse_source(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
+                self.parse_destination(&chunks[3])?,
             ),
             "DIV" => Instruction::Divide(
-                self.parse_source(chunks[1])?,
-                self.parse_source(chunks[2])?,
-                self.parse_destination(chunks[3])?,
+                self.parse_source(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
+                self.parse_destination(&chunks[3])?,
             ),
             "MOD" => Instruction::Modulo(
-                self.parse_source(chunks[1])?,
-                self.parse_source(chunks[2])?,
-                self.parse_destination(chunks[3])?,
+                self.parse_source(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
+                self.parse_destination(&chunks[3])?,
             ),
-            "JMP" => Instruction::Jump(self.parse_label(chunks[1])?),
+            "JMP" => Instruction::Jump(self.parse_label(&chunks[1])?),
             "JGT" => Instruction::JumpGreaterThan(
-                self.parse_source(chunks[1])?,
-                self.parse_source(chunks[2])?,
-                self.parse_label(chunks[3])?,
+                self.parse_source(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
+                self.parse_label(&chunks[3])?,
             ),
             "JEQ" => Instruction::JumpEqual(
-                self.parse_source(chunks[1])?,
-                self.parse_source(chunks[2])?,
-                self.parse_label(chunks[3])?,
+                self.parse_source(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
+                self.parse_label(&chunks[3])?,
             ),
             "JLT" => Instruction::JumpLessThan(
-                self.parse_source(chunks[1])?,
-                self.parse_source(chunks[2])?,
-                self.parse_label(chunks[3])?,
+                self.parse_source(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
+                self.parse_label(&chunks[3])?,
             ),
-            "CALL" => Instruction::Call(self.parse_label(chunks[1])?),
-            "TIME" => Instruction::Time(self.parse_destination(chunks[1])?),
+            "CALL" => match chunks[1].text.strip_prefix(HOST_PREFIX) {
+                Some(name) => Instruction::HostCall(self.parse_host_function(name, &chunks[1])?),
+                None => Instruction::Call(self.parse_label(&chunks[1])?),
+            },
+            "TIME" => Instruction::Time(self.parse_destination(&chunks[1])?),
             "SYS" => Instruction::Syscall(
-                self.parse_destination(chunks[1])?,
-                self.parse_source(chunks[2])?,
+                self.parse_destination(&chunks[1])?,
+                self.parse_source(&chunks[2])?,
                 self.parse_optional_source(chunks.get(3))?,
                 self.parse_optional_source(chunks.get(4))?,
                 self.parse_optional_source(chunks.get(5))?,
@@ -144,23 +247,28 @@ impl Parser {
                 self.parse_optional_source(chunks.get(7))?,
                 self.parse_optional_source(chunks.get(8))?,
             ),
-            "FAULT" => Instruction::Fault(self.parse_fault_message(chunks[1..].join(" "))?),
+            "FAULT" => Instruction::Fault(self.parse_fault_message(&chunks)?),
             _ => {
-                return Err(ParseError::new(
+                return Err(self.error_at(
                     &format!("Unknown instruction: {}", instruction_id),
-                    None,
+                    chunks[0].span,
                 ))
             }
         };
 
-        Ok(i)
+        let span = InstructionSpan {
+            span: chunks[0].span.to(chunks[chunks.len() - 1].span),
+            operands: chunks[1..].iter().map(|chunk| chunk.span).collect(),
+        };
+
+        Ok((i, span))
     }
 
-    fn parse_optional_source(&self, chunk: Option<&&str>) -> Result<Option<Source>, ParseError> {
+    fn parse_optional_source(&self, chunk: Option<&Token>) -> Result<Option<Source>, ParseError> {
         match chunk {
             None => Ok(None),
             Some(chunk) => {
-                if *chunk == "_" {
+                if chunk.text == "_" {
                     return Ok(None);
                 }
 
@@ -170,67 +278,138 @@ impl Parser {
         }
     }
 
-    fn parse_source(&self, chunk: &str) -> Result<Source, ParseError> {
+    fn check_operand_count(
+        &self,
+        instruction_id: &str,
+        (min, max): (usize, usize),
+        chunks: &[Token],
+    ) -> Result<(), ParseError> {
+        let found = chunks.len() - 1;
+        if (min..=max).contains(&found) {
+            return Ok(());
+        }
+
+        let expected = if min == max {
+            format!("{}", min)
+        } else if max == usize::MAX {
+            format!("at least {}", min)
+        } else {
+            format!("{} to {}", min, max)
+        };
+        let msg = format!(
+            "{} expects {} operand{}, found {}",
+            instruction_id,
+            expected,
+            if min == 1 && (max == 1 || max == usize::MAX) {
+                ""
+            } else {
+                "s"
+            },
+            found
+        );
+
+        // Point at the surplus operands, or at the whole instruction if some are missing
+        let span = if found > max {
+            chunks[max + 1].span.to(chunks[found].span)
+        } else {
+            chunks[0].span.to(chunks[found].span)
+        };
+
+        Err(self.error_at(&msg, span))
+    }
+
+    fn parse_source(&self, token: &Token) -> Result<Source, ParseError> {
+        let chunk = token.text;
         if chunk.is_empty() {
-            return Err(ParseError::new("Parameter should not be empty", None));
+            return Err(self.error_at("Parameter should not be empty", token.span));
         }
 
         // Is value
-        if chunk.starts_with("'") && chunk.ends_with("'") {
-            let text = chunk[1..chunk.len() - 1].to_string();
-            let value = text.parse::<i64>().unwrap();
-            return Ok(Source::Data(value));
+        if chunk.len() >= 2 && chunk.starts_with("'") && chunk.ends_with("'") {
+            let text = &chunk[1..chunk.len() - 1];
+            return match text.parse::<i64>() {
+                Ok(value) => Ok(Source::Data(value)),
+                Err(_) => Err(self.error_at(
+                    &format!("Invalid value: {} is not a 64-bit integer", chunk),
+                    token.span,
+                )),
+            };
+        }
+        if chunk.starts_with("'") {
+            return Err(self.error_at(
+                &format!("Invalid value: {} is missing a closing quote", chunk),
+                token.span,
+            ));
         }
 
         // Reference
         if let Some(text) = chunk.strip_prefix("&") {
-            let value = text.parse::<usize>().unwrap();
+            let value = self.parse_register(text, token)?;
             return Ok(Source::Reference(value));
         }
 
         // Should be an address
-        let text = chunk.to_string();
-        let value = text.parse::<usize>().unwrap();
+        let value = self.parse_register(chunk, token)?;
         Ok(Source::Address(value))
     }
 
-    fn parse_destination(&self, chunk: &str) -> Result<Destination, ParseError> {
+    fn parse_destination(&self, token: &Token) -> Result<Destination, ParseError> {
+        let chunk = token.text;
         if chunk.is_empty() {
-            return Err(ParseError::new("Parameter should not be empty", None));
+            return Err(self.error_at("Parameter should not be empty", token.span));
+        }
+        if chunk.starts_with("'") {
+            return Err(self.error_at(
+                "Destination cannot be a value, expected an address or a reference",
+                token.span,
+            ));
         }
 
         // Reference
         if let Some(text) = chunk.strip_prefix("&") {
-            let value = text.parse::<usize>().unwrap();
+            let value = self.parse_register(text, token)?;
             return Ok(Destination::Reference(value));
         }
 
         // Should be an address
-        let text = chunk.to_string();
-        let value = text.parse::<usize>().unwrap();
+        let value = self.parse_register(chunk, token)?;
         Ok(Destination::Address(value))
     }
 
-    fn parse_label(&mut self, chunk: &str) -> Result<Label, ParseError> {
+    fn parse_register(&self, text: &str, token: &Token) -> Result<usize, ParseError> {
+        text.parse::<usize>().map_err(|_| {
+            let kind = if token.text.starts_with("&") {
+                "reference"
+            } else {
+                "address"
+            };
+            self.error_at(
+                &format!("Invalid {}: {} is not a register number", kind, token.text),
+                token.span,
+            )
+        })
+    }
+
+    fn parse_label(&mut self, token: &Token) -> Result<Label, ParseError> {
+        let chunk = token.text;
         if chunk.is_empty() {
-            return Err(ParseError::new("Label should not be empty", None));
+            return Err(self.error_at("Label should not be empty", token.span));
         }
         if chunk.starts_with("&") {
-            return Err(ParseError::new(
-                "Label should not start with an ampersand",
-                None,
-            ));
+            return Err(self.error_at("Label should not start with an ampersand", token.span));
         }
         if chunk.ends_with(":") {
-            return Err(ParseError::new("Label should not end with a colon", None));
+            return Err(self.error_at("Label should not end with a colon", token.span));
         }
         if chunk.parse::<i32>().is_ok() {
-            return Err(ParseError::new("Label should not be a number", None));
+            return Err(self.error_at("Label should not be a number", token.span));
         }
         if chunk.starts_with("'") && chunk.ends_with("'") {
-            return Err(ParseError::new("Label cannot be a value", None));
+            return Err(self.error_at("Label cannot be a value", token.span));
         }
 
+        self.referenced_labels.insert(chunk.to_string());
+
         if let Some(address) = self.labels.get(chunk) {
             Ok(Label::Instruction(*address))
         } else {
@@ -238,10 +417,18 @@ impl Parser {
         }
     }
 
-    fn resolve_labels(&mut self, instructions: &mut [Instruction]) -> Result<(), ParseError> {
+    fn parse_host_function(&self, name: &str, token: &Token) -> Result<String, ParseError> {
+        if name.is_empty() {
+            return Err(self.error_at("Host function name should not be empty", token.span));
+        }
+
+        Ok(name.to_string())
+    }
+
+    fn resolve_labels(&mut self, instructions: &mut [Instruction]) {
         // Resolve unresolved labels
         // TODO: Only loop over unresolved labels, and refactor
-        for instruction in instructions.iter_mut() {
+        for (pc, instruction) in instructions.iter_mut().enumerate() {
             match instruction {
                 Instruction::Jump(label)
                 | Instruction::JumpGreaterThan(_, _, label)
@@ -252,41 +439,29 @@ impl Parser {
                         if let Some(label_id) = self.labels.get(label_name) {
                             *label = Label::Instruction(*label_id);
                         } else {
-                            return Err(ParseError::new(
-                                &format!("Unresolved label: {}", label_name),
-                                None,
-                            ));
+                            // Labels are always the last operand
+                            let span = self
+                                .source_map
+                                .instruction(pc)
+                                .and_then(|span| span.operands.last().copied());
+                            let message = format!("Unresolved label: {}", label_name);
+
+                            let error = match span {
+                                Some(span) => self.error_at(&message, span),
+                                None => ParseError::new(&message, None),
+                            };
+                            self.diagnostics.push(Diagnostic::error(error));
                         }
                     }
                 }
                 _ => {}
             }
         }
-
-        Ok(())
     }
 
     fn line_is_non_functional(&self, line: &str) -> bool {
-        if line.is_empty() {
-            return true;
-        }
-
-        for (i, char) in line.chars().enumerate() {
-            if !char.is_whitespace() {
-                if i == line.len() - 1 {
-                    return true; // End of line, ergo empty
-                }
-
-                // Not empty, let's check if it's a comment
-                if char == '/' && line.chars().nth(i + 1).unwrap() == '/' {
-                    return true;
-                }
-
-                break;
-            }
-        }
-
-        false
+        let code = line.trim_start();
+        code.is_empty() || code.starts_with("//")
     }
 
     fn line_is_label(&self, line: &str) -> bool {
@@ -301,28 +476,118 @@ impl Parser {
         false
     }
 
-    fn parse_fault_message(&self, message: String) -> Result<String, ParseError> {
-        if !message.starts_with("\"") || !message.ends_with("\"") {
-            return Err(ParseError::new(
-                "Fault message should be enclosed in double quotes",
-                None,
-            ));
+    fn parse_fault_message(&self, chunks: &[Token]) -> Result<String, ParseError> {
+        let message = chunks[1..]
+            .iter()
+            .map(|chunk| chunk.text)
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        if message.len() < 2 || !message.starts_with("\"") || !message.ends_with("\"") {
+            let first = chunks.get(1).unwrap_or(&chunks[0]);
+            let span = first.span.to(chunks[chunks.len() - 1].span);
+            return Err(self.error_at("Fault message should be enclosed in double quotes", span));
         }
 
         // Trim quotes
         Ok(message[1..message.len() - 1].to_string())
     }
+
+    fn error_at(&self, msg: &str, span: Span) -> ParseError {
+        ParseError::new(
+            msg,
+            Some(ParseErrorLineDetails {
+                file: self.file.clone(),
+                span,
+                contents: self.source_map.line(span.line).map(|line| line.to_string()),
+            }),
+        )
+    }
 }
 
-#[derive(Debug)]
+/// Prefix of `call` targets that are functions registered by the host.
+const HOST_PREFIX: &str = "host:";
+
+/// Returns the minimum and maximum number of operands an instruction accepts.
+fn operand_count(instruction_id: &str) -> Option<(usize, usize)> {
+    let count = match instruction_id {
+        "RET" => (0, 0),
+        "IN" | "OUT" | "COUT" | "JMP" | "CALL" | "TIME" => (1, 1),
+        "SET" | "CIN" => (2, 2),
+        "ADD" | "SUB" | "MUL" | "DIV" | "MOD" | "JGT" | "JEQ" | "JLT" => (3, 3),
+        "SYS" => (2, 8),
+        "FAULT" => (1, usize::MAX),
+        _ => return None,
+    };
+    Some(count)
+}
+
+/// Splits a line into whitespace separated tokens, ignoring any trailing comment.
+fn tokenize(line: &str, line_number: usize) -> Vec<Token<'_>> {
+    let code = match line.find("//") {
+        Some(index) => &line[..index],
+        None => line,
+    };
+
+    code.split_whitespace()
+        .map(|text| {
+            let offset = text.as_ptr() as usize - code.as_ptr() as usize;
+            let column = code[..offset].chars().count() + 1;
+            Token {
+                text,
+                span: Span::new(line_number, column, text.chars().count()),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub error: ParseError,
+}
+
+impl Diagnostic {
+    fn error(error: ParseError) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            error,
+        }
+    }
+
+    fn warning(error: ParseError) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            error,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.error),
+            Severity::Warning => write!(f, "warning: {}", self.error),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ParseError {
     error: String,
     line: Option<ParseErrorLineDetails>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParseErrorLineDetails {
-    line: usize,
+    file: PathBuf,
+    span: Span,
     contents: Option<String>,
 }
 
@@ -333,16 +598,19 @@ impl ParseError {
             line,
         }
     }
+
+    pub fn span(&self) -> Option<Span> {
+        self.line.as_ref().map(|line| line.span)
+    }
 }
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if let Some(line) = &self.line {
             write!(
                 f,
-                "{}\nLine: {}\nContents: {}",
+                "{}\n{}",
                 self.error,
-                line.line,
-                line.contents.as_deref().unwrap_or("")
+                render_excerpt(&line.file, line.span, line.contents.as_deref())
             )
         } else {
             write!(f, "{}", self.error)
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::instructions::{Instruction, Label};
+use crate::observer::{Observer, Outcome, State};
+use crate::program::Program;
+
+/// Name of the code outside of any `call`, and of instructions before the first label.
+const MAIN: &str = "<main>";
+
+/// Counts how often each instruction runs and attributes the counts to the
+/// `call` targets on the return stack, see `Profiler::report`.
+pub struct Profiler<'a> {
+    program: &'a Program,
+    /// Labels sorted by the instruction they point to.
+    labels: Vec<(usize, &'a str)>,
+    counts: Vec<u64>,
+    /// The call tree, rooted at `<main>`.
+    nodes: Vec<Node>,
+    children: HashMap<(usize, usize), usize>,
+    /// The node of the call currently executing.
+    current: usize,
+    calls: HashMap<usize, u64>,
+    pending: Pending,
+}
+
+struct Node {
+    parent: usize,
+    /// Instruction index of the call target, `None` for `<main>`.
+    function: Option<usize>,
+    /// Instructions executed in this call path, excluding nested calls.
+    count: u64,
+}
+
+#[derive(Clone, Copy)]
+enum Pending {
+    None,
+    Call(usize),
+    Return,
+}
+
+/// Instruction counts of a `call` target, or of `<main>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    /// Instructions executed while the function was on the return stack.
+    pub inclusive: u64,
+    /// Instructions executed in the function itself.
+    pub exclusive: u64,
+}
+
+impl<'a> Profiler<'a> {
+    pub fn new(program: &'a Program) -> Profiler<'a> {
+        let mut labels = program
+            .labels()
+            .iter()
+            .map(|(name, pc)| (*pc, name.as_str()))
+            .collect::<Vec<_>>();
+        labels.sort();
+
+        Profiler {
+            program,
+            labels,
+            counts: vec![0; program.instructions().len()],
+            nodes: vec![Node {
+                parent: 0,
+                function: None,
+                count: 0,
+            }],
+            children: HashMap::new(),
+            current: 0,
+            calls: HashMap::new(),
+            pending: Pending::None,
+        }
+    }
+
+    /// How many times each instruction was executed, indexed by pc.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Instruction counts per label-delimited block, most executed first. Every
+    /// instruction belongs to the closest label before it.
+    pub fn blocks(&self) -> Vec<(String, u64)> {
+        let mut blocks = HashMap::<&str, u64>::new();
+        for (pc, count) in self.counts.iter().enumerate() {
+            if *count > 0 {
+                *blocks.entry(self.block(pc)).or_default() += count;
+            }
+        }
+
+        let mut blocks = blocks
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect::<Vec<_>>();
+        blocks.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        blocks
+    }
+
+    /// Counts per `call` target, most inclusive first. Recursive calls are only
+    /// counted once towards the inclusive count.
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions = HashMap::<Option<usize>, FunctionProfile>::new();
+        let mut path = Vec::new();
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            path.clear();
+            let mut current = i;
+            loop {
+                let function = self.nodes[current].function;
+                if !path.contains(&function) {
+                    path.push(function);
+                }
+                if current == 0 {
+                    break;
+                }
+                current = self.nodes[current].parent;
+            }
+
+            for function in &path {
+                let profile = functions
+                    .entry(*function)
+                    .or_insert_with(|| FunctionProfile {
+                        name: self.function_name(*function).to_string(),
+                        calls: function.map_or(1, |pc| self.calls.get(&pc).copied().unwrap_or(0)),
+                        inclusive: 0,
+                        exclusive: 0,
+                    });
+                profile.inclusive += node.count;
+            }
+            if let Some(profile) = functions.get_mut(&node.function) {
+                profile.exclusive += node.count;
+            }
+        }
+
+        let mut functions = functions.into_values().collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            b.inclusive
+                .cmp(&a.inclusive)
+                .then_with(|| b.exclusive.cmp(&a.exclusive))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        functions
+    }
+
+    /// Renders the call targets, blocks and the `limit` most executed instructions.
+    pub fn report(&self, limit: usize) -> String {
+        let total = self.total();
+        let percent = |count: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                count as f64 * 100.0 / total as f64
+            }
+        };
+
+        let mut report = format!("Profile: {} instructions executed\n", total);
+
+        let _ = writeln!(
+            report,
+            "\n{:>12} {:>7} {:>12} {:>7} {:>10}  Function",
+            "Inclusive", "%", "Exclusive", "%", "Calls"
+        );
+        for function in self.functions() {
+            let _ = writeln!(
+                report,
+                "{:>12} {:>6.1}% {:>12} {:>6.1}% {:>10}  {}",
+                function.inclusive,
+                percent(function.inclusive),
+                function.exclusive,
+                percent(function.exclusive),
+                function.calls,
+                function.name
+            );
+        }
+
+        let _ = writeln!(report, "\n{:>12} {:>7}  Block", "Count", "%");
+        for (name, count) in self.blocks() {
+            let _ = writeln!(report, "{:>12} {:>6.1}%  {}", count, percent(count), name);
+        }
+
+        let mut instructions = (0..self.counts.len())
+            .filter(|pc| self.counts[*pc] > 0)
+            .collect::<Vec<_>>();
+        instructions.sort_by(|a, b| self.counts[*b].cmp(&self.counts[*a]).then(a.cmp(b)));
+
+        let _ = writeln!(
+            report,
+            "\n{:>12} {:>7} {:>6} {:>6}  Instruction",
+            "Count", "%", "Index", "Line"
+        );
+        let source_map = self.program.source_map();
+        for pc in instructions.into_iter().take(limit) {
+            let line = source_map.instruction(pc).map_or(0, |span| span.span.line);
+            let _ = writeln!(
+                report,
+                "{:>12} {:>6.1}% {:>6} {:>6}  {}",
+                self.counts[pc],
+                percent(self.counts[pc]),
+                pc,
+                line,
+                source_map.line(line).unwrap_or("").trim()
+            );
+        }
+
+        report
+    }
+
+    /// Writes one `<main>;OUTER;INNER count` line per call path, the folded stack
+    /// format read by flamegraph tools.
+    pub fn write_folded<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut lines = Vec::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.count == 0 {
+                continue;
+            }
+
+            let mut names = Vec::new();
+            let mut current = i;
+            loop {
+                names.push(self.function_name(self.nodes[current].function));
+                if current == 0 {
+                    break;
+                }
+                current = self.nodes[current].parent;
+            }
+            names.reverse();
+            lines.push(format!("{} {}", names.join(";"), node.count));
+        }
+
+        lines.sort();
+        for line in lines {
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn block(&self, pc: usize) -> &str {
+        let labels_before = self.labels.partition_point(|(label_pc, _)| *label_pc <= pc);
+        match labels_before.checked_sub(1) {
+            Some(i) => {
+                // Several labels can point at the same instruction, use the first.
+                let label_pc = self.labels[i].0;
+                let first = self.labels.partition_point(|(other, _)| *other < label_pc);
+                self.labels[first].1
+            }
+            None => MAIN,
+        }
+    }
+
+    fn function_name(&self, function: Option<usize>) -> &str {
+        let Some(pc) = function else {
+            return MAIN;
+        };
+        let i = self.labels.partition_point(|(label_pc, _)| *label_pc < pc);
+        match self.labels.get(i) {
+            Some((label_pc, name)) if *label_pc == pc => name,
+            _ => "<unknown>",
+        }
+    }
+}
+
+impl Observer for Profiler<'_> {
+    fn before_instruction(&mut self, pc: usize, instruction: &Instruction, _state: State) {
+        self.counts[pc] += 1;
+        self.nodes[self.current].count += 1;
+
+        self.pending = match instruction {
+            Instruction::Call(Label::Instruction(target)) => Pending::Call(*target),
+            Instruction::Return => Pending::Return,
+            _ => Pending::None,
+        };
+    }
+
+    fn after_instruction(&mut self, _pc: usize, outcome: Outcome) {
+        let pending = std::mem::replace(&mut self.pending, Pending::None);
+        if !matches!(outcome, Outcome::Continue(_)) {
+            return;
+        }
+
+        match pending {
+            Pending::None => {}
+            Pending::Call(target) => {
+                *self.calls.entry(target).or_default() += 1;
+
+                let next = self.nodes.len();
+                let parent = self.current;
+                self.current = *self.children.entry((parent, target)).or_insert(next);
+                if self.current == next {
+                    self.nodes.push(Node {
+                        parent,
+                        function: Some(target),
+                        count: 0,
+                    });
+                }
+            }
+            Pending::Return => self.current = self.nodes[self.current].parent,
+        }
+    }
+
+    fn observes_writes(&self) -> bool {
+        false
+    }
+}
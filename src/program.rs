@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::bounds;
+use crate::bytecode::{self, BytecodeError};
+use crate::instructions::Instruction;
+use crate::lower::{self, Op};
+use crate::parser::{ParseError, Parser};
+use crate::source::SourceMap;
+
+/// A parsed program with all labels resolved, ready to be run.
+#[derive(Debug, Clone)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    labels: HashMap<String, usize>,
+    source_map: SourceMap,
+    /// `instructions` lowered for the runner.
+    code: Vec<Op>,
+    /// See `Program::register_bounds`.
+    bounds: Vec<Option<usize>>,
+    /// Tells programs apart so the runner knows which one its state belongs to.
+    /// Clones share it, they are the same program.
+    id: u64,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+impl Program {
+    pub fn new(
+        instructions: Vec<Instruction>,
+        labels: HashMap<String, usize>,
+        source_map: SourceMap,
+    ) -> Program {
+        Program {
+            code: lower::lower(&instructions),
+            bounds: bounds::register_bounds(&instructions),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            instructions,
+            labels,
+            source_map,
+        }
+    }
+
+    /// Parses the program in `file`.
+    pub fn from_file(file: impl Into<PathBuf>) -> Result<Program, ParseError> {
+        Parser::new(file.into()).parse()
+    }
+
+    /// Parses program text held in memory.
+    pub fn from_source(source: &str) -> Result<Program, ParseError> {
+        Parser::from_source("<source>", source).parse()
+    }
+
+    /// Loads a program encoded with `Program::to_bytecode`. It has no source
+    /// locations, `file` only names the program in reports.
+    pub fn from_bytecode(bytes: &[u8], file: impl Into<PathBuf>) -> Result<Program, BytecodeError> {
+        bytecode::decode(bytes, file.into())
+    }
+
+    /// Encodes the program in the `.yaulc` format, see `bytecode::encode`.
+    pub fn to_bytecode(&self) -> Result<Vec<u8>, BytecodeError> {
+        bytecode::encode(self)
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Instruction index of every label defined in the program.
+    pub fn labels(&self) -> &HashMap<String, usize> {
+        &self.labels
+    }
+
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// The highest register each instruction can access, directly or through
+    /// a reference, where that can be proven. The runner skips bounds checks
+    /// for instructions whose bound is below the register count.
+    ///
+    /// The bounds hold when the program runs from the first instruction, whatever
+    /// the registers held at the start, until the registers are changed from outside.
+    pub fn register_bounds(&self) -> &[Option<usize>] {
+        &self.bounds
+    }
+
+    pub(crate) fn code(&self) -> &[Op] {
+        &self.code
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+}
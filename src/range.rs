@@ -0,0 +1,31 @@
+/// Parses an inclusive range of indices from `N`, `A..B`, `A..=B`, `A..` or `..B`.
+/// Open ends stretch to 0 and `usize::MAX`. `what` names the indices in errors,
+/// e.g. `Invalid register: x`.
+pub(crate) fn parse_range(s: &str, what: &str) -> Result<(usize, usize), String> {
+    let parse = |text: &str| {
+        text.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid {}: {}", what, text))
+    };
+
+    let Some((start, end)) = s.split_once("..") else {
+        let index = parse(s)?;
+        return Ok((index, index));
+    };
+
+    let start = if start.is_empty() { 0 } else { parse(start)? };
+    let end = if let Some(end) = end.strip_prefix('=') {
+        parse(end)?
+    } else if end.is_empty() {
+        usize::MAX
+    } else {
+        parse(end)?
+            .checked_sub(1)
+            .ok_or_else(|| format!("Empty range: {}", s))?
+    };
+
+    if end < start {
+        return Err(format!("Empty range: {}", s));
+    }
+    Ok((start, end))
+}
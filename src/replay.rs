@@ -0,0 +1,305 @@
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::runner::RuntimeErrorKind;
+use crate::syscall::SyscallError;
+
+const HEADER: &str = "yaul-replay";
+
+/// Bumped whenever the format changes. Older versions are rejected rather than guessed at.
+pub const VERSION: u32 = 1;
+
+/// A value produced by an instruction whose result depends on the outside world.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The line read by `IN`, including the line terminator. Empty at the end of input.
+    Input(String),
+    /// The bytes read by `CIN` when asked for `size` bytes.
+    CharInput { size: usize, bytes: Vec<u8> },
+    /// The nanoseconds returned by `TIME`.
+    Time(i64),
+    /// The result of `SYS` with the given syscall number.
+    Syscall {
+        number: i64,
+        result: Result<usize, SyscallError>,
+    },
+}
+
+/// What an instruction is about to consume, used to detect replays that diverge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Request {
+    Input,
+    CharInput(usize),
+    Time,
+    Syscall(i64),
+}
+
+/// The events of a run, in order. Written as a `yaul-replay <version>` header
+/// followed by one event per line:
+///
+/// ```text
+/// yaul-replay 1
+/// in "42\n"
+/// cin 3 "abc"
+/// time 1700000000000000000
+/// sys 39 1234
+/// sys 1 errno 9
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub events: Vec<Event>,
+}
+
+/// Whether a `Runner` records or replays events, see `RunnerBuilder::record`.
+pub(crate) enum ReplayMode {
+    Off,
+    /// Also writes each event to the log, if any, see `RunnerBuilder::record_to`.
+    Record(Recording, Option<Box<dyn Write + Send>>),
+    Replay {
+        recording: Recording,
+        next: usize,
+    },
+}
+
+impl ReplayMode {
+    /// Returns the next recorded event when replaying, or `None` if the value
+    /// should come from the outside world.
+    pub(crate) fn next(&mut self, request: Request) -> Result<Option<Event>, RuntimeErrorKind> {
+        let ReplayMode::Replay { recording, next } = self else {
+            return Ok(None);
+        };
+
+        let index = *next;
+        // A recording of a program that ended the process with a syscall stops
+        // right before it, so that one runs for real.
+        if index == recording.events.len() && matches!(request, Request::Syscall(_)) {
+            return Ok(None);
+        }
+        let Some(event) = recording.events.get(index) else {
+            return Err(RuntimeErrorKind::ReplayDivergence {
+                event: index,
+                message: format!("the program wants {} but the recording has ended", request),
+            });
+        };
+        if !event.answers(request) {
+            return Err(RuntimeErrorKind::ReplayDivergence {
+                event: index,
+                message: format!(
+                    "the program wants {} but the recording has {}",
+                    request, event
+                ),
+            });
+        }
+
+        *next += 1;
+        Ok(Some(event.clone()))
+    }
+
+    /// Adds `event` to the recording, if recording, and writes it to the log.
+    pub(crate) fn record(&mut self, event: Event) -> io::Result<()> {
+        let ReplayMode::Record(recording, log) = self else {
+            return Ok(());
+        };
+        if let Some(log) = log {
+            writeln!(log, "{}", event)?;
+            log.flush()?;
+        }
+        recording.events.push(event);
+        Ok(())
+    }
+
+    /// Starts recording or replaying from the first event again.
+    pub(crate) fn rewind(&mut self) {
+        match self {
+            ReplayMode::Off => {}
+            ReplayMode::Record(recording, _) => recording.events.clear(),
+            ReplayMode::Replay { next, .. } => *next = 0,
+        }
+    }
+}
+
+impl Event {
+    pub(crate) fn answers(&self, request: Request) -> bool {
+        match (self, request) {
+            (Event::Input(_), Request::Input) | (Event::Time(_), Request::Time) => true,
+            (Event::CharInput { size, .. }, Request::CharInput(wanted)) => *size == wanted,
+            (Event::Syscall { number, .. }, Request::Syscall(wanted)) => *number == wanted,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Request::Input => write!(f, "in"),
+            Request::CharInput(size) => write!(f, "cin {}", size),
+            Request::Time => write!(f, "time"),
+            Request::Syscall(number) => write!(f, "sys {}", number),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Input(line) => write!(f, "in {}", quote(line.as_bytes())),
+            Event::CharInput { size, bytes } => write!(f, "cin {} {}", size, quote(bytes)),
+            Event::Time(time) => write!(f, "time {}", time),
+            Event::Syscall { number, result } => match result {
+                Ok(value) => write!(f, "sys {} {}", number, value),
+                Err(SyscallError::Errno(errno)) => write!(f, "sys {} errno {}", number, errno),
+                Err(SyscallError::Unsupported) => write!(f, "sys {} unsupported", number),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Recording {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+
+        let header = lines.next().unwrap_or("");
+        let version = header
+            .strip_prefix(HEADER)
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or_else(|| "Not a YAUL replay file".to_string())?;
+        if version != VERSION {
+            return Err(format!(
+                "Unsupported replay version {}, expected {}",
+                version, VERSION
+            ));
+        }
+
+        let events = lines
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| parse_event(line).map_err(|e| format!("Line {}: {}", i + 2, e)))
+            .collect::<Result<Vec<Event>, String>>()?;
+
+        Ok(Recording { events })
+    }
+}
+
+fn parse_event(line: &str) -> Result<Event, String> {
+    let (kind, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+    let rest = rest.trim();
+
+    let number = |text: &str| {
+        text.parse::<i64>()
+            .map_err(|_| format!("Invalid number: {}", text))
+    };
+
+    match kind {
+        "in" => {
+            let line = String::from_utf8(unquote(rest)?)
+                .map_err(|_| "Input is not valid UTF-8".to_string())?;
+            Ok(Event::Input(line))
+        }
+        "cin" => {
+            let (size, bytes) = rest
+                .split_once(' ')
+                .ok_or_else(|| "Expected a size and a string".to_string())?;
+            let size = size
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid size: {}", size))?;
+            Ok(Event::CharInput {
+                size,
+                bytes: unquote(bytes.trim())?,
+            })
+        }
+        "time" => Ok(Event::Time(number(rest)?)),
+        "sys" => {
+            let words = rest.split_whitespace().collect::<Vec<&str>>();
+            let result = match words[..] {
+                [_, "unsupported"] => Err(SyscallError::Unsupported),
+                [_, "errno", errno] => Err(SyscallError::Errno(
+                    errno
+                        .parse::<i32>()
+                        .map_err(|_| format!("Invalid errno: {}", errno))?,
+                )),
+                [_, value] => Ok(value
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid result: {}", value))?),
+                _ => return Err("Expected a syscall number and a result".to_string()),
+            };
+            Ok(Event::Syscall {
+                number: number(words[0])?,
+                result,
+            })
+        }
+        _ => Err(format!("Unknown event: {}", kind)),
+    }
+}
+
+/// Quotes `bytes`, escaping anything that isn't printable ASCII.
+fn quote(bytes: &[u8]) -> String {
+    let mut quoted = String::with_capacity(bytes.len() + 2);
+    quoted.push('"');
+    for byte in bytes {
+        match byte {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            b' '..=b'~' => quoted.push(*byte as char),
+            _ => {
+                let _ = write!(quoted, "\\x{:02x}", byte);
+            }
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn unquote(text: &str) -> Result<Vec<u8>, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .filter(|_| text.len() >= 2)
+        .ok_or_else(|| format!("Expected a quoted string, found {}", text))?;
+
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut chars = inner.bytes();
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'"') => bytes.push(b'"'),
+            Some(b'\\') => bytes.push(b'\\'),
+            Some(b'x') => {
+                let hex = [chars.next(), chars.next()];
+                let hex = hex
+                    .iter()
+                    .map(|digit| digit.map(char::from))
+                    .collect::<Option<String>>()
+                    .ok_or_else(|| "Incomplete \\x escape".to_string())?;
+                bytes.push(
+                    u8::from_str_radix(&hex, 16)
+                        .map_err(|_| format!("Invalid escape: \\x{}", hex))?,
+                );
+            }
+            _ => return Err(format!("Invalid escape in {}", text)),
+        }
+    }
+    Ok(bytes)
+}
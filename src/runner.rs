@@ -1,277 +1,1220 @@
+use crate::history::{History, StackChange};
+use crate::host::{HostFunction, Trap};
 use crate::instructions::{Instruction, Label};
-use crate::syscall::syscall;
+use crate::io::{Io, StdIo};
+use crate::lower::Op;
+use crate::observer::{Observer, Outcome, State};
+use crate::program::Program;
+use crate::replay::{Event, Recording, ReplayMode, Request};
+use crate::snapshot::{Snapshot, SnapshotError};
+use crate::syscall::{syscall, SyscallError};
+use crate::watch::{Access, WatchHit, Watchpoint};
 
-use std::io::{self, Read};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
 
-pub struct Runner {
+pub struct Runner<I = StdIo, O = ()> {
     registers: Vec<i64>,
     stack: Vec<usize>,
+    /// Index of the next instruction to execute.
+    pc: usize,
+    /// Instructions left to execute, unlimited if `None`.
+    fuel: Option<u64>,
+    fast: bool,
+    /// The program whose register bounds hold for the current state, if any.
+    /// Cleared when the state is changed from outside.
+    verified: Option<u64>,
+    io: I,
+    observer: O,
+    host_functions: HashMap<String, HostFunction>,
+    watchpoints: Vec<Watchpoint>,
+    /// The first watched access of the instruction being executed.
+    watch_hit: Option<WatchHit>,
+    replay: ReplayMode,
+    history: Option<History>,
+    /// Events read by instructions undone with `step_back`, handed out again,
+    /// next one last, when they are executed again.
+    redo: Vec<Event>,
+    /// The event read by the instruction being executed.
+    consumed: Option<Event>,
 }
 
-impl Runner {
-    pub fn new(register_count: usize) -> Self {
+/// Configures a `Runner`, see `Runner::builder`.
+pub struct RunnerBuilder<I = StdIo, O = ()> {
+    registers: usize,
+    fuel: Option<u64>,
+    fast: bool,
+    io: I,
+    observer: O,
+    host_functions: HashMap<String, HostFunction>,
+    watchpoints: Vec<Watchpoint>,
+    replay: ReplayMode,
+    history: bool,
+}
+
+impl Default for RunnerBuilder {
+    fn default() -> Self {
         Self {
-            registers: vec![0; register_count],
+            registers: 8192,
+            fuel: None,
+            fast: false,
+            io: StdIo,
+            observer: (),
+            host_functions: HashMap::new(),
+            watchpoints: Vec::new(),
+            replay: ReplayMode::Off,
+            history: false,
+        }
+    }
+}
+
+impl<I: Io, O: Observer> RunnerBuilder<I, O> {
+    /// Number of registers available to the program. Defaults to 8192.
+    pub fn registers(mut self, registers: usize) -> Self {
+        self.registers = registers;
+        self
+    }
+
+    /// Limits how many instructions can be executed before the program is suspended
+    /// with `ExitStatus::OutOfFuel`. Unlimited by default.
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Skips bounds checks on register access for every instruction, not only
+    /// those `Program::register_bounds` proves safe.
+    ///
+    /// # Safety
+    ///
+    /// Every register the program reads or writes, directly or through a reference,
+    /// must be within the configured register count. Anything else is undefined behavior.
+    pub unsafe fn fast(mut self, fast: bool) -> Self {
+        self.fast = fast;
+        self
+    }
+
+    /// Where `IN`, `CIN`, `OUT` and `COUT` read and write. Defaults to stdio.
+    pub fn io<J: Io>(self, io: J) -> RunnerBuilder<J, O> {
+        RunnerBuilder {
+            registers: self.registers,
+            fuel: self.fuel,
+            fast: self.fast,
+            io,
+            observer: self.observer,
+            host_functions: self.host_functions,
+            watchpoints: self.watchpoints,
+            replay: self.replay,
+            history: self.history,
+        }
+    }
+
+    /// Hooks into execution, see `Observer`.
+    pub fn observer<P: Observer>(self, observer: P) -> RunnerBuilder<I, P> {
+        RunnerBuilder {
+            registers: self.registers,
+            fuel: self.fuel,
+            fast: self.fast,
+            io: self.io,
+            observer,
+            host_functions: self.host_functions,
+            watchpoints: self.watchpoints,
+            replay: self.replay,
+            history: self.history,
+        }
+    }
+
+    /// Makes `function` callable from the program with `call host:<name>`.
+    pub fn host_function<F>(mut self, name: &str, function: F) -> Self
+    where
+        F: FnMut(&mut [i64]) -> Result<(), Trap> + Send + 'static,
+    {
+        self.host_functions
+            .insert(name.to_string(), Box::new(function));
+        self
+    }
+
+    /// Suspends execution with `ExitStatus::Watchpoint` after an instruction
+    /// accesses a watched register.
+    pub fn watch(mut self, watchpoint: Watchpoint) -> Self {
+        self.watchpoints.push(watchpoint);
+        self
+    }
+
+    /// Records every value produced by `IN`, `CIN`, `TIME` and `SYS`, see `Runner::recording`.
+    pub fn record(mut self) -> Self {
+        self.replay = ReplayMode::Record(Recording::default(), None);
+        self
+    }
+
+    /// Records like `record` and also writes each event to `log` as soon as it
+    /// happens, one line each as in `Recording`. The events are kept even if the
+    /// program ends the process itself, e.g. with the `exit` syscall. The header
+    /// is not written, write an empty `Recording` first. Events of later runs are
+    /// appended.
+    pub fn record_to(mut self, log: impl io::Write + Send + 'static) -> Self {
+        self.replay = ReplayMode::Record(Recording::default(), Some(Box::new(log)));
+        self
+    }
+
+    /// Feeds `IN`, `CIN`, `TIME` and `SYS` the values from `recording` instead of
+    /// asking the outside world. Fails with `RuntimeErrorKind::ReplayDivergence` if
+    /// the program asks for something other than what was recorded next. A `SYS`
+    /// after the last event is executed, as it may be the one that ended the
+    /// recorded run.
+    pub fn replay(mut self, recording: Recording) -> Self {
+        self.replay = ReplayMode::Replay { recording, next: 0 };
+        self
+    }
+
+    /// Keeps an undo log of every executed instruction so execution can be
+    /// reversed with `Runner::step_back`. The log grows with every instruction.
+    pub fn history(mut self) -> Self {
+        self.history = true;
+        self
+    }
+
+    pub fn build(self) -> Runner<I, O> {
+        Runner {
+            registers: vec![0; self.registers],
             stack: Vec::new(),
+            pc: 0,
+            fuel: self.fuel,
+            fast: self.fast,
+            verified: None,
+            io: self.io,
+            observer: self.observer,
+            host_functions: self.host_functions,
+            watchpoints: self.watchpoints,
+            watch_hit: None,
+            replay: self.replay,
+            history: self.history.then(History::default),
+            redo: Vec::new(),
+            consumed: None,
+        }
+    }
+}
+
+/// How a program stopped when it did not fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Execution ran past the last instruction.
+    Completed,
+    /// `ret` was executed with an empty call stack.
+    Returned,
+    /// The instruction budget ran out. Add fuel and `resume` to continue.
+    OutOfFuel,
+    /// An instruction accessed a watched register. `resume` continues after it.
+    Watchpoint(WatchHit),
+}
+
+enum Flow {
+    Next,
+    Jump(usize),
+    Exit,
+}
+
+impl Runner {
+    pub fn new(register_count: usize) -> Self {
+        Self::builder().registers(register_count).build()
+    }
+
+    pub fn builder() -> RunnerBuilder {
+        RunnerBuilder::default()
+    }
+}
+
+impl<I: Io, O: Observer> Runner<I, O> {
+    pub fn io(&self) -> &I {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut I {
+        &mut self.io
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Consumes the runner, returning its observer.
+    pub fn into_observer(self) -> O {
+        self.observer
+    }
+
+    /// Makes `function` callable from the program with `call host:<name>`,
+    /// replacing any function already registered under that name.
+    pub fn register_host_function<F>(&mut self, name: &str, function: F)
+    where
+        F: FnMut(&mut [i64]) -> Result<(), Trap> + Send + 'static,
+    {
+        self.host_functions
+            .insert(name.to_string(), Box::new(function));
+    }
+
+    /// Suspends execution with `ExitStatus::Watchpoint` after an instruction
+    /// accesses a watched register.
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes a watchpoint, returning whether it existed.
+    pub fn unwatch(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// The undo log, if enabled with `RunnerBuilder::history` or `set_history`.
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Starts or stops keeping an undo log. Stopping discards the log.
+    pub fn set_history(&mut self, enabled: bool) {
+        match (enabled, &self.history) {
+            (true, None) => self.history = Some(History::default()),
+            (false, Some(_)) => {
+                self.history = None;
+                self.redo.clear();
+            }
+            _ => {}
         }
     }
 
-    pub fn run<const FAST: bool>(&mut self, instructions: &[Instruction]) {
+    /// Undoes the last executed instruction, restoring the registers it wrote,
+    /// the call stack, the pc and the fuel it used. Returns the pc of the undone
+    /// instruction, or `None` at the start of the history. Output already
+    /// written is not undone. Input already read is kept and handed to the
+    /// instruction again when it is executed again.
+    pub fn step_back(&mut self) -> Option<usize> {
+        let history = self.history.as_mut()?;
+        let registers = &mut self.registers;
+        let redo = &mut self.redo;
+        let (pc, stack) = history.pop(
+            |register, old| registers[register] = old,
+            |event| redo.push(event),
+        )?;
+
+        match stack {
+            StackChange::None => {}
+            StackChange::Pushed => {
+                self.stack.pop();
+            }
+            StackChange::Popped(address) => self.stack.push(address),
+        }
+        self.pc = pc;
+        // The history may reach back past changes made from outside.
+        self.verified = None;
+        if let Some(fuel) = &mut self.fuel {
+            *fuel += 1;
+        }
+
+        Some(pc)
+    }
+
+    /// The events recorded so far, or being replayed. `None` unless the runner
+    /// was built with `record` or `replay`.
+    pub fn recording(&self) -> Option<&Recording> {
+        match &self.replay {
+            ReplayMode::Off => None,
+            ReplayMode::Record(recording, _) | ReplayMode::Replay { recording, .. } => {
+                Some(recording)
+            }
+        }
+    }
+
+    pub fn registers(&self) -> &[i64] {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [i64] {
+        self.verified = None;
+        &mut self.registers
+    }
+
+    /// Sets `register` from outside the program. Unlike a change through
+    /// `registers_mut` the old value goes into the history, so stepping back over
+    /// the last executed instruction undoes this too. Returns `false` if there is
+    /// no such register.
+    pub fn set_register(&mut self, register: usize, value: i64) -> bool {
+        let Some(slot) = self.registers.get_mut(register) else {
+            return false;
+        };
+        let old = std::mem::replace(slot, value);
+        self.verified = None;
+        // Before the first instruction there is nothing to step back to.
+        if let Some(history) = self.history.as_mut().filter(|history| !history.is_empty()) {
+            history.write(register, old);
+        }
+        true
+    }
+
+    /// Return addresses of the active calls, innermost last.
+    pub fn stack(&self) -> &[usize] {
+        &self.stack
+    }
+
+    /// Index of the next instruction to execute.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Instructions left to execute, `None` if unlimited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Tops up the instruction budget. Does nothing if fuel is unlimited.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(current) = &mut self.fuel {
+            *current = current.saturating_add(fuel);
+        }
+    }
+
+    /// Runs the program from the start with all registers zeroed.
+    pub fn run(&mut self, program: &Program) -> Result<ExitStatus, RuntimeError> {
+        self.reset();
+        self.resume(program)
+    }
+
+    /// Zeroes all registers, clears the call stack and moves back to the first
+    /// instruction. Recording or replaying starts over and the history is cleared.
+    pub fn reset(&mut self) {
         self.registers.fill(0);
         self.stack.clear();
+        self.pc = 0;
+        self.replay.rewind();
+        self.redo.clear();
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    /// Captures the registers, call stack, program counter and fuel so execution
+    /// can continue later with `restore`. The history is not included.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.registers.clone(),
+            stack: self.stack.clone(),
+            pc: self.pc,
+            fuel: self.fuel,
+        }
+    }
 
-        let mut pc = 0;
+    /// Replaces the machine state with `snapshot`, after which `resume` continues
+    /// where the snapshot was taken. The snapshot must have as many registers as the runner.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if snapshot.registers.len() != self.registers.len() {
+            return Err(SnapshotError::RegisterCount {
+                expected: self.registers.len(),
+                found: snapshot.registers.len(),
+            });
+        }
+
+        self.registers.copy_from_slice(&snapshot.registers);
+        self.verified = None;
+        self.stack.clone_from(&snapshot.stack);
+        self.pc = snapshot.pc;
+        self.fuel = snapshot.fuel;
+        self.redo.clear();
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        Ok(())
+    }
+
+    /// Executes only the next instruction. Returns `None` if the program can continue.
+    pub fn step(&mut self, program: &Program) -> Result<Option<ExitStatus>, RuntimeError> {
+        if self.fuel == Some(0) {
+            return Ok(Some(ExitStatus::OutOfFuel));
+        }
+
+        let fuel = self.fuel.replace(1);
+        let result = self.resume(program);
+        let used = u64::from(self.fuel == Some(0));
+        self.fuel = fuel.map(|fuel| fuel - used);
+
+        match result? {
+            ExitStatus::OutOfFuel => Ok(None),
+            status => Ok(Some(status)),
+        }
+    }
+
+    /// Continues running the program from where it was suspended, keeping the
+    /// registers, call stack and program counter.
+    pub fn resume(&mut self, program: &Program) -> Result<ExitStatus, RuntimeError> {
+        let track = !self.watchpoints.is_empty() || self.history.is_some();
+        match (self.fast, track) {
+            (true, true) => self.execute::<true, true>(program),
+            (true, false) => self.execute::<true, false>(program),
+            (false, true) => self.execute::<false, true>(program),
+            (false, false) => self.execute::<false, false>(program),
+        }
+    }
+
+    fn execute<const FAST: bool, const TRACK: bool>(
+        &mut self,
+        program: &Program,
+    ) -> Result<ExitStatus, RuntimeError> {
+        let instructions = program.instructions();
+        let code = program.code();
+        let mut pc = self.pc;
         let max_pc = instructions.len();
 
+        // The bounds only hold for states the program can get into by itself.
+        let bounds = program.register_bounds();
+        let verified = (pc == 0 && self.stack.is_empty()) || self.verified == Some(program.id());
+        self.verified = verified.then(|| program.id());
+        let limit = if verified { self.registers.len() } else { 0 };
+
         loop {
             if pc >= max_pc {
-                break;
+                self.pc = pc;
+                return Ok(ExitStatus::Completed);
+            }
+
+            if let Some(fuel) = &mut self.fuel {
+                if *fuel == 0 {
+                    self.pc = pc;
+                    return Ok(ExitStatus::OutOfFuel);
+                }
+                *fuel -= 1;
             }
 
             let instruction = &instructions[pc];
 
-            match instruction {
-                Instruction::Return => {
-                    if self.stack.is_empty() {
-                        // Returning from main works as program exit.
-                        return;
-                    }
+            let state = State {
+                registers: &self.registers,
+                stack: &self.stack,
+            };
+            self.observer.before_instruction(pc, instruction, state);
 
-                    pc = self.stack.pop().unwrap();
-                    continue;
+            let (depth, top) = (self.stack.len(), self.stack.last().copied());
+            if TRACK {
+                self.pc = pc;
+                self.consumed = None;
+                if let Some(history) = &mut self.history {
+                    history.begin(pc);
                 }
-                Instruction::Set(value, destination) => {
-                    let _value = self.read_source::<FAST>(value);
-                    let _destination = self.read_destination::<FAST>(destination);
+            }
 
-                    self.write_reg::<FAST>(_destination, _value);
-                }
-                Instruction::Input(destination) => {
-                    let mut input = String::new();
-                    std::io::stdin().read_line(&mut input).unwrap();
-                    let input = input.trim().parse::<i64>().unwrap();
+            let checked = !FAST && bounds[pc].is_none_or(|bound| bound >= limit);
+            let result = match (code[pc], checked) {
+                (Op::Other, true) => self.dispatch::<FAST, TRACK>(instruction, pc),
+                (Op::Other, false) => self.dispatch::<true, TRACK>(instruction, pc),
+                (op, true) => self.dispatch_op::<FAST, TRACK>(op, pc),
+                (op, false) => self.dispatch_op::<true, TRACK>(op, pc),
+            };
 
-                    let _destination = self.read_destination::<FAST>(destination);
-                    self.write_reg::<FAST>(_destination, input);
+            if TRACK {
+                self.record_stack_change(depth, top);
+                if let (Some(history), Some(event)) = (&mut self.history, self.consumed.take()) {
+                    history.event(event);
                 }
-                Instruction::CharInput(destination, size) => {
-                    let _size = self.read_source::<FAST>(size);
-                    if _size < 0 {
-                        panic!("Cin size must be positive");
-                    }
-
-                    let stdin = io::stdin();
-                    let mut buffer = vec![0; _size as usize];
-                    let bytes_read = stdin.lock().read(&mut buffer).unwrap();
+            }
 
-                    buffer.truncate(bytes_read); // In case less than x bytes were read
-                    let result = String::from_utf8(buffer).expect("Found invalid UTF-8");
+            let outcome = match result {
+                Ok(Flow::Next) => Outcome::Continue(pc + 1),
+                Ok(Flow::Jump(target)) => Outcome::Continue(target),
+                Ok(Flow::Exit) => Outcome::Exit,
+                Err(_) => Outcome::Failed,
+            };
+            self.observer.after_instruction(pc, outcome);
 
-                    let _destination = self.read_destination::<FAST>(destination);
-                    for (i, c) in result.chars().enumerate() {
-                        self.write_reg::<FAST>(_destination + i, c as i64);
-                    }
+            match result {
+                Ok(Flow::Next) => pc += 1,
+                Ok(Flow::Jump(target)) => pc = target,
+                Ok(Flow::Exit) => {
+                    self.watch_hit = None;
+                    self.pc = max_pc;
+                    return Ok(ExitStatus::Returned);
                 }
-                Instruction::Output(value) => {
-                    let _value = self.read_source::<FAST>(value);
-
-                    println!("{}", _value);
+                Err(kind) => {
+                    // The instruction may have stopped halfway.
+                    self.watch_hit = None;
+                    self.verified = None;
+                    self.pc = pc;
+                    return Err(RuntimeError {
+                        kind,
+                        pc,
+                        instruction: Box::new(instruction.clone()),
+                        stack: self.stack.clone(),
+                    });
                 }
-                Instruction::CharOutput(value) => {
-                    let _value = self.read_source::<FAST>(value);
+            }
 
-                    print!("{}", _value as u8 as char);
+            if TRACK {
+                if let Some(hit) = self.watch_hit.take() {
+                    self.pc = pc;
+                    return Ok(ExitStatus::Watchpoint(hit));
                 }
-                Instruction::Add(addend1, addend2, destination) => {
-                    let _addend1 = self.read_source::<FAST>(addend1);
-                    let _addend2 = self.read_source::<FAST>(addend2);
-                    let _destination = self.read_destination::<FAST>(destination);
+            }
+        }
+    }
 
-                    self.write_reg::<FAST>(_destination, _addend1 + _addend2);
-                }
-                Instruction::Subtract(minuend, subtrahend, destination) => {
-                    let _minuend = self.read_source::<FAST>(minuend);
-                    let _subtrahend = self.read_source::<FAST>(subtrahend);
-                    let _destination = self.read_destination::<FAST>(destination);
+    /// Executes a lowered instruction, the same way `dispatch` would execute
+    /// the instruction it was lowered from.
+    #[inline(always)]
+    fn dispatch_op<const FAST: bool, const TRACK: bool>(
+        &mut self,
+        op: Op,
+        pc: usize,
+    ) -> Result<Flow, RuntimeErrorKind> {
+        match op {
+            Op::Return => return Ok(self.stack.pop().map_or(Flow::Exit, Flow::Jump)),
+            Op::SetImm(value, d) => self.write_reg::<FAST, TRACK>(d, value)?,
+            Op::SetReg(a, d) => {
+                let a = self.read_reg::<FAST, TRACK>(a)?;
+                self.write_reg::<FAST, TRACK>(d, a)?;
+            }
+            Op::AddRegReg(a, b, d) => {
+                let (a, b) = self.read_regs::<FAST, TRACK>(a, b)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_add(b))?;
+            }
+            Op::AddRegImm(a, b, d) => {
+                let a = self.read_reg::<FAST, TRACK>(a)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_add(b))?;
+            }
+            Op::AddImmReg(a, b, d) => {
+                let b = self.read_reg::<FAST, TRACK>(b)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_add(b))?;
+            }
+            Op::SubRegReg(a, b, d) => {
+                let (a, b) = self.read_regs::<FAST, TRACK>(a, b)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_sub(b))?;
+            }
+            Op::SubRegImm(a, b, d) => {
+                let a = self.read_reg::<FAST, TRACK>(a)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_sub(b))?;
+            }
+            Op::SubImmReg(a, b, d) => {
+                let b = self.read_reg::<FAST, TRACK>(b)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_sub(b))?;
+            }
+            Op::MulRegReg(a, b, d) => {
+                let (a, b) = self.read_regs::<FAST, TRACK>(a, b)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_mul(b))?;
+            }
+            Op::MulRegImm(a, b, d) => {
+                let a = self.read_reg::<FAST, TRACK>(a)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_mul(b))?;
+            }
+            Op::MulImmReg(a, b, d) => {
+                let b = self.read_reg::<FAST, TRACK>(b)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_mul(b))?;
+            }
+            Op::DivRegReg(a, b, d) => {
+                let (a, b) = self.read_regs::<FAST, TRACK>(a, b)?;
+                self.write_reg::<FAST, TRACK>(d, checked_div(a, b)?)?;
+            }
+            Op::DivRegImm(a, b, d) => {
+                let a = self.read_reg::<FAST, TRACK>(a)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_div(b))?;
+            }
+            Op::DivImmReg(a, b, d) => {
+                let b = self.read_reg::<FAST, TRACK>(b)?;
+                self.write_reg::<FAST, TRACK>(d, checked_div(a, b)?)?;
+            }
+            Op::ModRegReg(a, b, d) => {
+                let (a, b) = self.read_regs::<FAST, TRACK>(a, b)?;
+                self.write_reg::<FAST, TRACK>(d, checked_rem(a, b)?)?;
+            }
+            Op::ModRegImm(a, b, d) => {
+                let a = self.read_reg::<FAST, TRACK>(a)?;
+                self.write_reg::<FAST, TRACK>(d, a.wrapping_rem(b))?;
+            }
+            Op::ModImmReg(a, b, d) => {
+                let b = self.read_reg::<FAST, TRACK>(b)?;
+                self.write_reg::<FAST, TRACK>(d, checked_rem(a, b)?)?;
+            }
+            Op::Jump(target) => return Ok(Flow::Jump(target)),
+            Op::JgtRegReg(a, b, target) => {
+                let (a, b) = self.read_regs::<FAST, TRACK>(a, b)?;
+                return Ok(branch(a > b, target));
+            }
+            Op::JgtRegImm(a, b, target) => {
+                let a = self.read_reg::<FAST, TRACK>(a)?;
+                return Ok(branch(a > b, target));
+            }
+            Op::JgtImmReg(a, b, target) => {
+                let b = self.read_reg::<FAST, TRACK>(b)?;
+                return Ok(branch(a > b, target));
+            }
+            Op::JeqRegReg(a, b, target) => {
+                let (a, b) = self.read_regs::<FAST, TRACK>(a, b)?;
+                return Ok(branch(a == b, target));
+            }
+            Op::JeqRegImm(a, b, target) => {
+                let a = self.read_reg::<FAST, TRACK>(a)?;
+                return Ok(branch(a == b, target));
+            }
+            Op::JeqImmReg(a, b, target) => {
+                let b = self.read_reg::<FAST, TRACK>(b)?;
+                return Ok(branch(a == b, target));
+            }
+            Op::JltRegReg(a, b, target) => {
+                let (a, b) = self.read_regs::<FAST, TRACK>(a, b)?;
+                return Ok(branch(a < b, target));
+            }
+            Op::JltRegImm(a, b, target) => {
+                let a = self.read_reg::<FAST, TRACK>(a)?;
+                return Ok(branch(a < b, target));
+            }
+            Op::JltImmReg(a, b, target) => {
+                let b = self.read_reg::<FAST, TRACK>(b)?;
+                return Ok(branch(a < b, target));
+            }
+            Op::Call(target) => {
+                self.stack.push(pc + 1);
+                return Ok(Flow::Jump(target));
+            }
+            Op::Other => unreachable!("executed with dispatch"),
+        }
 
-                    self.write_reg::<FAST>(_destination, _minuend - _subtrahend);
-                }
-                Instruction::Multiply(factor1, factor2, destination) => {
-                    let _factor1 = self.read_source::<FAST>(factor1);
-                    let _factor2 = self.read_source::<FAST>(factor2);
-                    let _destination = self.read_destination::<FAST>(destination);
+        Ok(Flow::Next)
+    }
 
-                    self.write_reg::<FAST>(_destination, _factor1 * _factor2);
-                }
-                Instruction::Divide(dividend, divisor, destination) => {
-                    let _dividend = self.read_source::<FAST>(dividend);
-                    let _divisor = self.read_source::<FAST>(divisor);
-                    let _destination = self.read_destination::<FAST>(destination);
+    #[inline(always)]
+    fn dispatch<const FAST: bool, const TRACK: bool>(
+        &mut self,
+        instruction: &Instruction,
+        pc: usize,
+    ) -> Result<Flow, RuntimeErrorKind> {
+        match instruction {
+            Instruction::Return => {
+                // Returning from main works as program exit.
+                return Ok(self.stack.pop().map_or(Flow::Exit, Flow::Jump));
+            }
+            Instruction::Set(value, destination) => {
+                let _value = self.read_source::<FAST, TRACK>(value)?;
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
 
-                    self.write_reg::<FAST>(_destination, _dividend / _divisor);
-                }
-                Instruction::Modulo(dividend, divisor, destination) => {
-                    let _dividend = self.read_source::<FAST>(dividend);
-                    let _divisor = self.read_source::<FAST>(divisor);
-                    let _destination = self.read_destination::<FAST>(destination);
+                self.write_reg::<FAST, TRACK>(_destination, _value)?;
+            }
+            Instruction::Input(destination) => {
+                let input = match self.next_event(Request::Input)? {
+                    Some(Event::Input(line)) => line,
+                    _ => {
+                        let mut line = String::new();
+                        self.io.read_line(&mut line)?;
+                        self.record_event(Event::Input(line.clone()))?;
+                        line
+                    }
+                };
+                let input = input
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| RuntimeErrorKind::InvalidInput(input.trim().to_string()))?;
 
-                    self.write_reg::<FAST>(_destination, _dividend % _divisor);
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
+                self.write_reg::<FAST, TRACK>(_destination, input)?;
+            }
+            Instruction::CharInput(destination, size) => {
+                let _size = self.read_source::<FAST, TRACK>(size)?;
+                if _size < 0 {
+                    return Err(RuntimeErrorKind::InvalidInputSize(_size));
                 }
-                Instruction::Jump(label) => {
-                    let _label = match label {
-                        Label::Label(_) => panic!("Invalid label"),
-                        Label::Instruction(value) => *value,
-                    };
-
-                    pc = _label;
-                    continue;
+
+                // Never read more than fits in the registers after the destination.
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
+                if _size > 0 && _destination >= self.registers.len() {
+                    return Err(RuntimeErrorKind::RegisterOutOfBounds(_destination));
                 }
-                Instruction::JumpGreaterThan(a, b, label) => {
-                    let _a = self.read_source::<FAST>(a);
-                    let _b = self.read_source::<FAST>(b);
-                    let _label = match label {
-                        Label::Label(_) => panic!("Invalid label"),
-                        Label::Instruction(value) => *value,
-                    };
-
-                    if _a > _b {
-                        pc = _label;
-                        continue;
+                let size = (_size as usize).min(self.registers.len().saturating_sub(_destination));
+                let buffer = match self.next_event(Request::CharInput(size))? {
+                    Some(Event::CharInput { mut bytes, .. }) => {
+                        bytes.truncate(size);
+                        bytes
                     }
-                }
-                Instruction::JumpEqual(a, b, label) => {
-                    let _a = self.read_source::<FAST>(a);
-                    let _b = self.read_source::<FAST>(b);
-                    let _label = match label {
-                        Label::Label(_) => panic!("Invalid label"),
-                        Label::Instruction(value) => *value,
-                    };
-
-                    if _a == _b {
-                        pc = _label;
-                        continue;
+                    _ => {
+                        let mut buffer = vec![0; size];
+                        let bytes_read = self.io.read(&mut buffer)?;
+
+                        buffer.truncate(bytes_read); // In case less than x bytes were read
+                        self.record_event(Event::CharInput {
+                            size,
+                            bytes: buffer.clone(),
+                        })?;
+                        buffer
                     }
+                };
+                let result =
+                    String::from_utf8(buffer).map_err(|_| RuntimeErrorKind::InvalidUtf8)?;
+
+                for (i, c) in result.chars().enumerate() {
+                    let register = _destination
+                        .checked_add(i)
+                        .ok_or(RuntimeErrorKind::RegisterOutOfBounds(usize::MAX))?;
+                    self.write_reg::<FAST, TRACK>(register, c as i64)?;
                 }
-                Instruction::JumpLessThan(a, b, label) => {
-                    let _a = self.read_source::<FAST>(a);
-                    let _b = self.read_source::<FAST>(b);
-                    let _label = match label {
-                        Label::Label(_) => panic!("Invalid label"),
-                        Label::Instruction(value) => *value,
-                    };
-
-                    if _a < _b {
-                        pc = _label;
-                        continue;
-                    }
+            }
+            Instruction::Output(value) => {
+                let _value = self.read_source::<FAST, TRACK>(value)?;
+
+                self.io.write(format!("{}\n", _value).as_bytes())?;
+            }
+            Instruction::CharOutput(value) => {
+                let _value = self.read_source::<FAST, TRACK>(value)?;
+
+                let mut buffer = [0; 4];
+                let _char = (_value as u8 as char).encode_utf8(&mut buffer);
+                self.io.write(_char.as_bytes())?;
+            }
+            Instruction::Add(addend1, addend2, destination) => {
+                let _addend1 = self.read_source::<FAST, TRACK>(addend1)?;
+                let _addend2 = self.read_source::<FAST, TRACK>(addend2)?;
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
+
+                self.write_reg::<FAST, TRACK>(_destination, _addend1.wrapping_add(_addend2))?;
+            }
+            Instruction::Subtract(minuend, subtrahend, destination) => {
+                let _minuend = self.read_source::<FAST, TRACK>(minuend)?;
+                let _subtrahend = self.read_source::<FAST, TRACK>(subtrahend)?;
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
+
+                self.write_reg::<FAST, TRACK>(_destination, _minuend.wrapping_sub(_subtrahend))?;
+            }
+            Instruction::Multiply(factor1, factor2, destination) => {
+                let _factor1 = self.read_source::<FAST, TRACK>(factor1)?;
+                let _factor2 = self.read_source::<FAST, TRACK>(factor2)?;
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
+
+                self.write_reg::<FAST, TRACK>(_destination, _factor1.wrapping_mul(_factor2))?;
+            }
+            Instruction::Divide(dividend, divisor, destination) => {
+                let _dividend = self.read_source::<FAST, TRACK>(dividend)?;
+                let _divisor = self.read_source::<FAST, TRACK>(divisor)?;
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
+
+                if _divisor == 0 {
+                    return Err(RuntimeErrorKind::DivisionByZero);
                 }
-                Instruction::Call(label) => {
-                    let _label = match label {
-                        Label::Label(_) => panic!("Invalid label"),
-                        Label::Instruction(value) => *value,
-                    };
-
-                    self.stack.push(pc + 1);
-                    pc = _label;
-                    continue;
+
+                self.write_reg::<FAST, TRACK>(_destination, _dividend.wrapping_div(_divisor))?;
+            }
+            Instruction::Modulo(dividend, divisor, destination) => {
+                let _dividend = self.read_source::<FAST, TRACK>(dividend)?;
+                let _divisor = self.read_source::<FAST, TRACK>(divisor)?;
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
+
+                if _divisor == 0 {
+                    return Err(RuntimeErrorKind::DivisionByZero);
                 }
-                Instruction::Time(destination) => {
-                    let _destination = self.read_destination::<FAST>(destination);
 
-                    let time = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_nanos();
+                self.write_reg::<FAST, TRACK>(_destination, _dividend.wrapping_rem(_divisor))?;
+            }
+            Instruction::Jump(label) => {
+                return Ok(Flow::Jump(resolved_label(label)?));
+            }
+            Instruction::JumpGreaterThan(a, b, label) => {
+                let _a = self.read_source::<FAST, TRACK>(a)?;
+                let _b = self.read_source::<FAST, TRACK>(b)?;
+                let _label = resolved_label(label)?;
 
-                    self.write_reg::<FAST>(_destination, time as i64);
+                if _a > _b {
+                    return Ok(Flow::Jump(_label));
                 }
-                Instruction::Syscall(destination, sysno, a1, a2, a3, a4, a5, a6) => {
-                    let _destination = self.read_destination::<FAST>(destination);
-                    let _sysno = self.read_source::<FAST>(sysno);
-
-                    let _a1 = self.read_optional_source::<FAST>(a1);
-                    let _a2 = self.read_optional_source::<FAST>(a2);
-                    let _a3 = self.read_optional_source::<FAST>(a3);
-                    let _a4 = self.read_optional_source::<FAST>(a4);
-                    let _a5 = self.read_optional_source::<FAST>(a5);
-                    let _a6 = self.read_optional_source::<FAST>(a6);
-
-                    let ret = unsafe {
-                        syscall(
-                            _sysno as usize,
-                            optional_int_to_usize(_a1),
-                            optional_int_to_usize(_a2),
-                            optional_int_to_usize(_a3),
-                            optional_int_to_usize(_a4),
-                            optional_int_to_usize(_a5),
-                            optional_int_to_usize(_a6),
-                        )
-                    };
-
-                    // https://git.musl-libc.org/cgit/musl/tree/src/internal/syscall_ret.c?h=v1.1.15
-                    if ret > -4096isize as usize {
-                        let errno = -(ret as i32);
-                        panic!("syscall failed: {}", errno)
-                    } else {
-                        self.write_reg::<FAST>(_destination, ret as i64);
-                    }
+            }
+            Instruction::JumpEqual(a, b, label) => {
+                let _a = self.read_source::<FAST, TRACK>(a)?;
+                let _b = self.read_source::<FAST, TRACK>(b)?;
+                let _label = resolved_label(label)?;
+
+                if _a == _b {
+                    return Ok(Flow::Jump(_label));
                 }
-                Instruction::Fault(msg) => {
-                    panic!("Program fault: {}", msg);
+            }
+            Instruction::JumpLessThan(a, b, label) => {
+                let _a = self.read_source::<FAST, TRACK>(a)?;
+                let _b = self.read_source::<FAST, TRACK>(b)?;
+                let _label = resolved_label(label)?;
+
+                if _a < _b {
+                    return Ok(Flow::Jump(_label));
                 }
             }
+            Instruction::Call(label) => {
+                let _label = resolved_label(label)?;
 
-            pc += 1;
+                self.stack.push(pc + 1);
+                return Ok(Flow::Jump(_label));
+            }
+            Instruction::HostCall(name) => {
+                let function = self
+                    .host_functions
+                    .get_mut(name)
+                    .ok_or_else(|| RuntimeErrorKind::UnknownHostFunction(name.clone()))?;
+
+                // Host functions change registers directly. Only when something is
+                // watching for writes, note afterwards what they changed.
+                let result = if TRACK || self.observer.observes_writes() {
+                    let before = self.registers.clone();
+                    let result = function(&mut self.registers);
+                    self.host_writes(&before);
+                    result
+                } else {
+                    function(&mut self.registers)
+                };
+                result.map_err(|trap| RuntimeErrorKind::HostTrap {
+                    function: name.clone(),
+                    message: trap.message,
+                })?;
+            }
+            Instruction::Time(destination) => {
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
+
+                let time = match self.next_event(Request::Time)? {
+                    Some(Event::Time(time)) => time,
+                    _ => {
+                        let time = std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_nanos() as i64;
+                        self.record_event(Event::Time(time))?;
+                        time
+                    }
+                };
+
+                self.write_reg::<FAST, TRACK>(_destination, time)?;
+            }
+            Instruction::Syscall(destination, sysno, a1, a2, a3, a4, a5, a6) => {
+                let _destination = self.read_destination::<FAST, TRACK>(destination)?;
+                let _sysno = self.read_source::<FAST, TRACK>(sysno)?;
+
+                let _a1 = self.read_optional_source::<FAST, TRACK>(a1)?;
+                let _a2 = self.read_optional_source::<FAST, TRACK>(a2)?;
+                let _a3 = self.read_optional_source::<FAST, TRACK>(a3)?;
+                let _a4 = self.read_optional_source::<FAST, TRACK>(a4)?;
+                let _a5 = self.read_optional_source::<FAST, TRACK>(a5)?;
+                let _a6 = self.read_optional_source::<FAST, TRACK>(a6)?;
+
+                let result = match self.next_event(Request::Syscall(_sysno))? {
+                    Some(Event::Syscall { result, .. }) => result,
+                    _ => {
+                        let result = unsafe {
+                            syscall(
+                                _sysno as usize,
+                                optional_int_to_usize(_a1),
+                                optional_int_to_usize(_a2),
+                                optional_int_to_usize(_a3),
+                                optional_int_to_usize(_a4),
+                                optional_int_to_usize(_a5),
+                                optional_int_to_usize(_a6),
+                            )
+                        };
+                        self.record_event(Event::Syscall {
+                            number: _sysno,
+                            result,
+                        })?;
+                        result
+                    }
+                };
+                let ret = result?;
+
+                self.write_reg::<FAST, TRACK>(_destination, ret as i64)?;
+            }
+            Instruction::Fault(msg) => {
+                return Err(RuntimeErrorKind::Fault(msg.clone()));
+            }
         }
+
+        Ok(Flow::Next)
     }
 
-    fn read_optional_source<const FAST: bool>(
-        &self,
+    fn read_optional_source<const FAST: bool, const TRACK: bool>(
+        &mut self,
         param: &Option<crate::instructions::Source>,
-    ) -> Option<i64> {
-        param.as_ref().map(|value| self.read_source::<FAST>(value))
+    ) -> Result<Option<i64>, RuntimeErrorKind> {
+        param
+            .as_ref()
+            .map(|value| self.read_source::<FAST, TRACK>(value))
+            .transpose()
     }
 
-    fn read_source<const FAST: bool>(&self, param: &crate::instructions::Source) -> i64 {
+    fn read_source<const FAST: bool, const TRACK: bool>(
+        &mut self,
+        param: &crate::instructions::Source,
+    ) -> Result<i64, RuntimeErrorKind> {
         match param {
-            crate::instructions::Source::Data(value) => *value,
-            crate::instructions::Source::Address(value) => self.read_reg::<FAST>(*value),
+            crate::instructions::Source::Data(value) => Ok(*value),
+            crate::instructions::Source::Address(value) => self.read_reg::<FAST, TRACK>(*value),
             crate::instructions::Source::Reference(value) => {
-                let referenced_reg = self.read_reg::<FAST>(*value);
-                self.read_reg::<FAST>(referenced_reg as usize)
+                let referenced_reg = self.read_reg::<FAST, TRACK>(*value)?;
+                self.read_reg::<FAST, TRACK>(referenced_reg as usize)
             }
         }
     }
 
-    fn read_destination<const FAST: bool>(
-        &self,
+    fn read_destination<const FAST: bool, const TRACK: bool>(
+        &mut self,
         param: &crate::instructions::Destination,
-    ) -> usize {
+    ) -> Result<usize, RuntimeErrorKind> {
         match param {
-            crate::instructions::Destination::Address(value) => *value,
+            crate::instructions::Destination::Address(value) => Ok(*value),
             crate::instructions::Destination::Reference(value) => {
-                self.read_reg::<FAST>(*value) as usize
+                Ok(self.read_reg::<FAST, TRACK>(*value)? as usize)
             }
         }
     }
 
-    fn read_reg<const FAST: bool>(&self, i: usize) -> i64 {
-        if FAST {
+    /// Reads `a` and then `b`.
+    #[inline(always)]
+    fn read_regs<const FAST: bool, const TRACK: bool>(
+        &mut self,
+        a: usize,
+        b: usize,
+    ) -> Result<(i64, i64), RuntimeErrorKind> {
+        Ok((
+            self.read_reg::<FAST, TRACK>(a)?,
+            self.read_reg::<FAST, TRACK>(b)?,
+        ))
+    }
+
+    fn read_reg<const FAST: bool, const TRACK: bool>(
+        &mut self,
+        i: usize,
+    ) -> Result<i64, RuntimeErrorKind> {
+        let value = if FAST {
             unsafe { *self.registers.get_unchecked(i) }
         } else {
-            self.registers[i]
+            *self
+                .registers
+                .get(i)
+                .ok_or(RuntimeErrorKind::RegisterOutOfBounds(i))?
+        };
+        self.observer.register_read(i, value);
+        if TRACK {
+            self.check_watchpoints(i, Access::Read, value, value);
         }
+        Ok(value)
     }
 
-    fn write_reg<const FAST: bool>(&mut self, i: usize, value: i64) {
-        if FAST {
-            unsafe { *self.registers.get_unchecked_mut(i) = value }
+    fn write_reg<const FAST: bool, const TRACK: bool>(
+        &mut self,
+        i: usize,
+        value: i64,
+    ) -> Result<(), RuntimeErrorKind> {
+        let register = if FAST {
+            unsafe { self.registers.get_unchecked_mut(i) }
         } else {
-            self.registers[i] = value;
+            self.registers
+                .get_mut(i)
+                .ok_or(RuntimeErrorKind::RegisterOutOfBounds(i))?
+        };
+        let old = std::mem::replace(register, value);
+        self.observer.register_written(i, old, value);
+        if TRACK {
+            if let Some(history) = &mut self.history {
+                history.write(i, old);
+            }
+            self.check_watchpoints(i, Access::Write, old, value);
         }
+        Ok(())
+    }
+
+    /// Treats every register that differs from `before` as written by the
+    /// instruction, like `write_reg` would.
+    fn host_writes(&mut self, before: &[i64]) {
+        let changes = before
+            .iter()
+            .zip(&self.registers)
+            .enumerate()
+            .filter(|(_, (old, value))| old != value)
+            .map(|(i, (old, value))| (i, *old, *value))
+            .collect::<Vec<(usize, i64, i64)>>();
+        for (i, old, value) in changes {
+            self.observer.register_written(i, old, value);
+            if let Some(history) = &mut self.history {
+                history.write(i, old);
+            }
+            self.check_watchpoints(i, Access::Write, old, value);
+        }
+    }
+
+    /// The recorded answer to `request`, if any: an event read before the
+    /// instruction was undone, or the next event of the recording being replayed.
+    /// `None` means it has to be read from the outside world and recorded.
+    fn next_event(&mut self, request: Request) -> Result<Option<Event>, RuntimeErrorKind> {
+        let event = match self.redo.pop() {
+            Some(event) if event.answers(request) => Some(event),
+            // The program took another path, the rest can't be trusted either.
+            _ => {
+                self.redo.clear();
+                self.replay.next(request)?
+            }
+        };
+        self.consumed.clone_from(&event);
+        Ok(event)
+    }
+
+    fn record_event(&mut self, event: Event) -> io::Result<()> {
+        self.consumed = Some(event.clone());
+        self.replay.record(event)
+    }
+
+    /// Notes in the history whether the instruction that just ran pushed or
+    /// popped, given the stack depth and top before it.
+    fn record_stack_change(&mut self, depth: usize, top: Option<usize>) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        let change = match (self.stack.len().cmp(&depth), top) {
+            (Ordering::Greater, _) => StackChange::Pushed,
+            (Ordering::Less, Some(top)) => StackChange::Popped(top),
+            _ => return,
+        };
+        history.set_stack_change(change);
+    }
+
+    fn check_watchpoints(&mut self, register: usize, access: Access, old: i64, value: i64) {
+        if self.watch_hit.is_none()
+            && self
+                .watchpoints
+                .iter()
+                .any(|watchpoint| watchpoint.matches(register, access))
+        {
+            self.watch_hit = Some(WatchHit {
+                pc: self.pc,
+                register,
+                access,
+                old,
+                value,
+            });
+        }
+    }
+}
+
+fn resolved_label(label: &Label) -> Result<usize, RuntimeErrorKind> {
+    match label {
+        Label::Label(name) => Err(RuntimeErrorKind::UnresolvedLabel(name.clone())),
+        Label::Instruction(value) => Ok(*value),
     }
 }
 
+fn branch(jumps: bool, target: usize) -> Flow {
+    if jumps {
+        Flow::Jump(target)
+    } else {
+        Flow::Next
+    }
+}
+
+fn checked_div(dividend: i64, divisor: i64) -> Result<i64, RuntimeErrorKind> {
+    if divisor == 0 {
+        return Err(RuntimeErrorKind::DivisionByZero);
+    }
+    Ok(dividend.wrapping_div(divisor))
+}
+
+fn checked_rem(dividend: i64, divisor: i64) -> Result<i64, RuntimeErrorKind> {
+    if divisor == 0 {
+        return Err(RuntimeErrorKind::DivisionByZero);
+    }
+    Ok(dividend.wrapping_rem(divisor))
+}
+
 fn optional_int_to_usize(value: Option<i64>) -> Option<usize> {
     value.map(|value| value as usize)
 }
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    /// Index of the instruction that failed.
+    pub pc: usize,
+    pub instruction: Box<Instruction>,
+    /// Return addresses of the active calls, innermost last.
+    pub stack: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    RegisterOutOfBounds(usize),
+    InvalidInput(String),
+    InvalidInputSize(i64),
+    InvalidUtf8,
+    UnresolvedLabel(String),
+    Io(io::Error),
+    Syscall(SyscallError),
+    Fault(String),
+    UnknownHostFunction(String),
+    HostTrap {
+        function: String,
+        message: String,
+    },
+    /// A replayed program asked for something other than the next recorded event.
+    ReplayDivergence {
+        event: usize,
+        message: String,
+    },
+}
+
+impl From<io::Error> for RuntimeErrorKind {
+    fn from(e: io::Error) -> Self {
+        RuntimeErrorKind::Io(e)
+    }
+}
+
+impl From<SyscallError> for RuntimeErrorKind {
+    fn from(e: SyscallError) -> Self {
+        RuntimeErrorKind::Syscall(e)
+    }
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            RuntimeErrorKind::RegisterOutOfBounds(i) => {
+                write!(f, "Register out of bounds: {}", i)
+            }
+            RuntimeErrorKind::InvalidInput(input) => write!(f, "Invalid input: {:?}", input),
+            RuntimeErrorKind::InvalidInputSize(size) => {
+                write!(f, "Cin size must be positive, got {}", size)
+            }
+            RuntimeErrorKind::InvalidUtf8 => write!(f, "Found invalid UTF-8"),
+            RuntimeErrorKind::UnresolvedLabel(label) => write!(f, "Unresolved label: {}", label),
+            RuntimeErrorKind::Io(e) => write!(f, "I/O error: {}", e),
+            RuntimeErrorKind::Syscall(e) => write!(f, "Syscall failed: {}", e),
+            RuntimeErrorKind::Fault(msg) => write!(f, "Program fault: {}", msg),
+            RuntimeErrorKind::UnknownHostFunction(name) => {
+                write!(f, "Unknown host function: {}", name)
+            }
+            RuntimeErrorKind::HostTrap { function, message } => {
+                write!(f, "Host function {} trapped: {}", function, message)
+            }
+            RuntimeErrorKind::ReplayDivergence { event, message } => {
+                write!(f, "Replay diverged at event {}: {}", event, message)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\nInstruction {}: {:?}\nStack: {:?}",
+            self.kind, self.pc, self.instruction, self.stack
+        )
+    }
+}
+
+impl Error for RuntimeError {}
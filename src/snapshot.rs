@@ -0,0 +1,157 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 8] = b"YAULSNAP";
+
+/// Bumped whenever the encoding changes. Older versions are rejected rather than guessed at.
+pub const VERSION: u16 = 1;
+
+/// The state of a suspended `Runner`, see `Runner::snapshot` and `Runner::restore`.
+///
+/// Encoded as the magic bytes `YAULSNAP`, a little-endian `u16` version and then
+/// the pc, the fuel, the registers and the stack, each as little-endian 64-bit
+/// integers. The fuel is preceded by a byte that is 0 for unlimited fuel, and
+/// the registers and the stack by their length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub registers: Vec<i64>,
+    /// Return addresses of the active calls, innermost last.
+    pub stack: Vec<usize>,
+    /// Index of the next instruction to execute.
+    pub pc: usize,
+    /// Instructions left to execute, unlimited if `None`.
+    pub fuel: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    /// A length or index does not fit in memory on this machine.
+    TooLarge(u64),
+    /// The snapshot was taken with a different number of registers than the runner has.
+    RegisterCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            MAGIC.len() + 2 + 8 + 9 + 8 * (2 + self.registers.len() + self.stack.len()),
+        );
+        // Writing to a Vec can't fail.
+        let _ = self.write_to(&mut bytes);
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        Snapshot::read_from(&mut bytes)
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        writer.write_all(&(self.pc as u64).to_le_bytes())?;
+        match self.fuel {
+            Some(fuel) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&fuel.to_le_bytes())?;
+            }
+            None => writer.write_all(&[0; 9])?,
+        }
+
+        writer.write_all(&(self.registers.len() as u64).to_le_bytes())?;
+        for register in &self.registers {
+            writer.write_all(&register.to_le_bytes())?;
+        }
+
+        writer.write_all(&(self.stack.len() as u64).to_le_bytes())?;
+        for pc in &self.stack {
+            writer.write_all(&(*pc as u64).to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Snapshot, SnapshotError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let pc = read_usize(&mut reader)?;
+        let mut has_fuel = [0];
+        reader.read_exact(&mut has_fuel)?;
+        let fuel = read_u64(&mut reader)?;
+        let fuel = (has_fuel[0] != 0).then_some(fuel);
+
+        // Lengths come from the file, so grow the vectors as values are read
+        // instead of trusting them with an allocation up front.
+        let registers = (0..read_u64(&mut reader)?)
+            .map(|_| read_u64(&mut reader).map(|value| value as i64))
+            .collect::<Result<Vec<i64>, SnapshotError>>()?;
+        let stack = (0..read_u64(&mut reader)?)
+            .map(|_| read_usize(&mut reader))
+            .collect::<Result<Vec<usize>, SnapshotError>>()?;
+
+        Ok(Snapshot {
+            registers,
+            stack,
+            pc,
+            fuel,
+        })
+    }
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, SnapshotError> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_usize<R: Read>(reader: &mut R) -> Result<usize, SnapshotError> {
+    let value = read_u64(reader)?;
+    usize::try_from(value).map_err(|_| SnapshotError::TooLarge(value))
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                write!(f, "Snapshot is truncated")
+            }
+            SnapshotError::Io(e) => write!(f, "I/O error: {}", e),
+            SnapshotError::InvalidMagic => write!(f, "Not a YAUL snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported snapshot version {}, expected {}",
+                version, VERSION
+            ),
+            SnapshotError::TooLarge(value) => write!(f, "Snapshot value too large: {}", value),
+            SnapshotError::RegisterCount { expected, found } => {
+                write!(f, "Snapshot has {} registers, expected {}", found, expected)
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {}
@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+
+/// A range of characters on a single source line. Lines and columns are 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, length: usize) -> Span {
+        Span {
+            line,
+            column,
+            length,
+        }
+    }
+
+    /// Returns a span covering both `self` and `other`, which must be on the same line.
+    pub fn to(self, other: Span) -> Span {
+        let end = (other.column + other.length).max(self.column + self.length);
+        let column = self.column.min(other.column);
+        Span::new(self.line, column, end - column)
+    }
+}
+
+/// Where an instruction and each of its operands came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionSpan {
+    pub span: Span,
+    pub operands: Vec<Span>,
+}
+
+/// Maps instruction indices back to the source they were parsed from.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    file: PathBuf,
+    lines: Vec<String>,
+    instructions: Vec<InstructionSpan>,
+}
+
+impl SourceMap {
+    pub fn new(file: PathBuf) -> SourceMap {
+        SourceMap {
+            file,
+            lines: Vec::new(),
+            instructions: Vec::new(),
+        }
+    }
+
+    /// The file the program was parsed from.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    pub fn push_line(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    pub fn push_instruction(&mut self, span: InstructionSpan) {
+        self.instructions.push(span);
+    }
+
+    /// Drops the spans of the instructions for which `keep` is false, see `optimize`.
+    pub(crate) fn retain_instructions(&mut self, keep: &[bool]) {
+        let mut pc = 0;
+        self.instructions.retain(|_| {
+            pc += 1;
+            keep.get(pc - 1).copied().unwrap_or(true)
+        });
+    }
+
+    /// Returns the contents of a 1-based source line.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.lines
+            .get(line.checked_sub(1)?)
+            .map(|line| line.as_str())
+    }
+
+    /// Returns the span of the instruction at `pc`.
+    pub fn instruction(&self, pc: usize) -> Option<&InstructionSpan> {
+        self.instructions.get(pc)
+    }
+
+    /// Renders the source line of `span` with the span underlined.
+    pub fn excerpt(&self, span: Span) -> String {
+        render_excerpt(&self.file, span, self.line(span.line))
+    }
+}
+
+/// Renders a `file:line:column` header followed by the line contents with the span
+/// underlined by carets.
+pub fn render_excerpt(file: &Path, span: Span, contents: Option<&str>) -> String {
+    let location = format!(" --> {}:{}:{}", file.display(), span.line, span.column);
+    let Some(contents) = contents else {
+        return location;
+    };
+
+    let gutter = " ".repeat(span.line.to_string().len());
+
+    // Reuse the whitespace of the line itself so tabs keep the carets aligned.
+    let padding = contents
+        .chars()
+        .take(span.column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let carets = "^".repeat(span.length.max(1));
+
+    format!(
+        "{location}\n{gutter} |\n{} | {contents}\n{gutter} | {padding}{carets}",
+        span.line
+    )
+}
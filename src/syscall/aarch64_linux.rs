@@ -0,0 +1,113 @@
+use core::arch::asm;
+
+// Syscalls for linux aarch64 use the generic syscall table:
+// https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/unistd.h
+
+// Arguments are passed in x0-x5, the syscall number in x8 and the call is made with svc 0.
+// https://man7.org/linux/man-pages/man2/syscall.2.html
+
+pub unsafe fn syscall0(n: usize) -> usize {
+    let mut ret: usize;
+    asm!(
+        "svc 0",
+        in("x8") n,
+        lateout("x0") ret,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn syscall1(n: usize, a1: usize) -> usize {
+    let mut ret: usize;
+    asm!(
+        "svc 0",
+        in("x8") n,
+        in("x0") a1,
+        lateout("x0") ret,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn syscall2(n: usize, a1: usize, a2: usize) -> usize {
+    let mut ret: usize;
+    asm!(
+        "svc 0",
+        in("x8") n,
+        in("x0") a1,
+        in("x1") a2,
+        lateout("x0") ret,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn syscall3(n: usize, a1: usize, a2: usize, a3: usize) -> usize {
+    let mut ret: usize;
+    asm!(
+        "svc 0",
+        in("x8") n,
+        in("x0") a1,
+        in("x1") a2,
+        in("x2") a3,
+        lateout("x0") ret,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn syscall4(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    let mut ret: usize;
+    asm!(
+        "svc 0",
+        in("x8") n,
+        in("x0") a1,
+        in("x1") a2,
+        in("x2") a3,
+        in("x3") a4,
+        lateout("x0") ret,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn syscall5(n: usize, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    let mut ret: usize;
+    asm!(
+        "svc 0",
+        in("x8") n,
+        in("x0") a1,
+        in("x1") a2,
+        in("x2") a3,
+        in("x3") a4,
+        in("x4") a5,
+        lateout("x0") ret,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+pub unsafe fn syscall6(
+    n: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+    a6: usize,
+) -> usize {
+    let mut ret: usize;
+    asm!(
+        "svc 0",
+        in("x8") n,
+        in("x0") a1,
+        in("x1") a2,
+        in("x2") a3,
+        in("x3") a4,
+        in("x4") a5,
+        in("x5") a6,
+        lateout("x0") ret,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
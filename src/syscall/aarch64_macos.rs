@@ -1,5 +1,10 @@
 use core::arch::asm;
 
+// Syscalls for macos aarch64 share numbers with macos x86_64:
+// https://github.com/apple-oss-distributions/xnu/blob/main/bsd/kern/syscalls.master
+
+// The syscall number is passed in x16 and the call is made with svc 0x80.
+
 // macOS syscalls have an offset of 0x2000000 (SYSCALL_CLASS_UNIX << SYSCALL_CLASS_SHIFT)
 // https://github.com/apple-oss-distributions/xnu/blob/main/osfmk/mach/syscall_sw.h
 const SYSCALL_SHIFT: usize = 0x2000000;
@@ -1,8 +1,16 @@
-// Support for aarch64
-#[cfg(target_arch = "aarch64")]
-mod aarch64;
-#[cfg(target_arch = "aarch64")]
-use aarch64::*;
+use std::fmt;
+
+// Support for macos aarch64
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+mod aarch64_macos;
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+use aarch64_macos::*;
+
+// Support for linux aarch64
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+mod aarch64_linux;
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+use aarch64_linux::*;
 
 // Support for macos x86_64
 #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
@@ -10,10 +18,47 @@ mod x86_64_macos;
 #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
 use x86_64_macos::*;
 
+// Support for linux x86_64
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod x86_64_linux;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+use x86_64_linux::*;
+
 type Arg = Option<usize>;
 
-pub unsafe fn syscall(n: usize, a1: Arg, a2: Arg, a3: Arg, a4: Arg, a5: Arg, a6: Arg) -> usize {
-    match (a1, a2, a3, a4, a5, a6) {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallError {
+    /// The syscall returned an error number.
+    Errno(i32),
+    /// There is no syscall backend for the target platform.
+    Unsupported,
+}
+
+impl fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyscallError::Errno(errno) => write!(f, "{}", errno),
+            SyscallError::Unsupported => write!(f, "syscalls are not supported on this platform"),
+        }
+    }
+}
+
+#[cfg(any(
+    all(target_os = "macos", target_arch = "aarch64"),
+    all(target_os = "linux", target_arch = "aarch64"),
+    all(target_os = "macos", target_arch = "x86_64"),
+    all(target_os = "linux", target_arch = "x86_64"),
+))]
+pub unsafe fn syscall(
+    n: usize,
+    a1: Arg,
+    a2: Arg,
+    a3: Arg,
+    a4: Arg,
+    a5: Arg,
+    a6: Arg,
+) -> Result<usize, SyscallError> {
+    let ret = match (a1, a2, a3, a4, a5, a6) {
         (Some(a1), Some(a2), Some(a3), Some(a4), Some(a5), Some(a6)) => {
             syscall6(n, a1, a2, a3, a4, a5, a6)
         }
@@ -23,5 +68,30 @@ pub unsafe fn syscall(n: usize, a1: Arg, a2: Arg, a3: Arg, a4: Arg, a5: Arg, a6:
         (Some(a1), Some(a2), _, _, _, _) => syscall2(n, a1, a2),
         (Some(a1), _, _, _, _, _) => syscall1(n, a1),
         (_, _, _, _, _, _) => syscall0(n),
+    };
+
+    // https://git.musl-libc.org/cgit/musl/tree/src/internal/syscall_ret.c?h=v1.1.15
+    if ret > -4096isize as usize {
+        Err(SyscallError::Errno(-(ret as i32)))
+    } else {
+        Ok(ret)
     }
 }
+
+#[cfg(not(any(
+    all(target_os = "macos", target_arch = "aarch64"),
+    all(target_os = "linux", target_arch = "aarch64"),
+    all(target_os = "macos", target_arch = "x86_64"),
+    all(target_os = "linux", target_arch = "x86_64"),
+)))]
+pub unsafe fn syscall(
+    _n: usize,
+    _a1: Arg,
+    _a2: Arg,
+    _a3: Arg,
+    _a4: Arg,
+    _a5: Arg,
+    _a6: Arg,
+) -> Result<usize, SyscallError> {
+    Err(SyscallError::Unsupported)
+}
@@ -0,0 +1,254 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::instructions::{Destination, Instruction, Label, Source};
+use crate::observer::{Observer, Outcome, State};
+use crate::range::parse_range;
+
+/// Writes one JSON object per executed instruction, see `Tracer::new`.
+pub struct Tracer<W: Write> {
+    writer: W,
+    filter: TraceFilter,
+    steps: u64,
+    current: Option<Record>,
+    error: Option<io::Error>,
+}
+
+/// Selects which instructions end up in a trace. Empty lists match everything.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    pub pc_ranges: Vec<PcRange>,
+    /// Upper case opcodes, e.g. `ADD`.
+    pub opcodes: Vec<String>,
+}
+
+/// An inclusive range of instruction indices, parsed from `N`, `A..B`, `A..=B`, `A..` or `..B`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+struct Record {
+    step: u64,
+    pc: usize,
+    opcode: &'static str,
+    operands: String,
+    writes: Vec<(usize, i64)>,
+    depth: usize,
+}
+
+impl<W: Write> Tracer<W> {
+    /// Each line looks like
+    /// `{"step":3,"pc":2,"op":"ADD","operands":[1,5,{"reg":1}],"writes":[{"reg":1,"value":6}],"depth":0,"next":3}`
+    ///
+    /// Sources are resolved to the values they read, destinations to the register
+    /// they write and labels to the instruction they jump to. `next` is the
+    /// instruction executed afterwards, or `"exit"`/`"failed"`.
+    pub fn new(writer: W, filter: TraceFilter) -> Tracer<W> {
+        Tracer {
+            writer,
+            filter,
+            steps: 0,
+            current: None,
+            error: None,
+        }
+    }
+
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// The first error hit while writing the trace, after which tracing stops.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Flushes the trace and hands back the writer, or returns the first error
+    /// hit while writing it.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_record(&mut self, record: Record, outcome: Outcome) {
+        let mut line = format!(
+            "{{\"step\":{},\"pc\":{},\"op\":\"{}\",\"operands\":[{}],\"writes\":[",
+            record.step, record.pc, record.opcode, record.operands
+        );
+        for (i, (register, value)) in record.writes.iter().enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+            let _ = write!(line, "{{\"reg\":{},\"value\":{}}}", register, value);
+        }
+        let _ = write!(line, "],\"depth\":{},\"next\":", record.depth);
+        match outcome {
+            Outcome::Continue(pc) => {
+                let _ = write!(line, "{}", pc);
+            }
+            Outcome::Exit => line.push_str("\"exit\""),
+            Outcome::Failed => line.push_str("\"failed\""),
+        }
+        line.push_str("}\n");
+
+        if let Err(e) = self.writer.write_all(line.as_bytes()) {
+            self.error = Some(e);
+        }
+    }
+}
+
+impl<W: Write> Observer for Tracer<W> {
+    fn before_instruction(&mut self, pc: usize, instruction: &Instruction, state: State) {
+        self.steps += 1;
+        if self.error.is_some() || !self.filter.matches(pc, instruction.opcode()) {
+            return;
+        }
+
+        self.current = Some(Record {
+            step: self.steps - 1,
+            pc,
+            opcode: instruction.opcode(),
+            operands: operands(instruction, state),
+            writes: Vec::new(),
+            depth: state.stack.len(),
+        });
+    }
+
+    fn register_written(&mut self, register: usize, _old: i64, new: i64) {
+        if let Some(record) = &mut self.current {
+            record.writes.push((register, new));
+        }
+    }
+
+    fn after_instruction(&mut self, _pc: usize, outcome: Outcome) {
+        if let Some(record) = self.current.take() {
+            self.write_record(record, outcome);
+        }
+    }
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: usize, opcode: &str) -> bool {
+        (self.pc_ranges.is_empty() || self.pc_ranges.iter().any(|range| range.contains(pc)))
+            && (self.opcodes.is_empty() || self.opcodes.iter().any(|op| op == opcode))
+    }
+}
+
+impl PcRange {
+    pub fn contains(&self, pc: usize) -> bool {
+        (self.start..=self.end).contains(&pc)
+    }
+}
+
+impl FromStr for PcRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = parse_range(s, "instruction index")?;
+        Ok(PcRange { start, end })
+    }
+}
+
+/// Renders the operands of `instruction` as the inside of a JSON array.
+fn operands(instruction: &Instruction, state: State) -> String {
+    let mut values = Vec::new();
+    match instruction {
+        Instruction::Return => {}
+        Instruction::Set(value, destination) => {
+            values.push(source(value, state));
+            values.push(destination_register(destination, state));
+        }
+        Instruction::Input(destination) | Instruction::Time(destination) => {
+            values.push(destination_register(destination, state));
+        }
+        Instruction::CharInput(destination, size) => {
+            values.push(destination_register(destination, state));
+            values.push(source(size, state));
+        }
+        Instruction::Output(value) | Instruction::CharOutput(value) => {
+            values.push(source(value, state));
+        }
+        Instruction::Add(a, b, destination)
+        | Instruction::Subtract(a, b, destination)
+        | Instruction::Multiply(a, b, destination)
+        | Instruction::Divide(a, b, destination)
+        | Instruction::Modulo(a, b, destination) => {
+            values.push(source(a, state));
+            values.push(source(b, state));
+            values.push(destination_register(destination, state));
+        }
+        Instruction::Jump(label) | Instruction::Call(label) => {
+            values.push(target(label));
+        }
+        Instruction::JumpGreaterThan(a, b, label)
+        | Instruction::JumpEqual(a, b, label)
+        | Instruction::JumpLessThan(a, b, label) => {
+            values.push(source(a, state));
+            values.push(source(b, state));
+            values.push(target(label));
+        }
+        Instruction::HostCall(name) => values.push(string(&format!("host:{}", name))),
+        Instruction::Fault(message) => values.push(string(message)),
+        Instruction::Syscall(destination, sysno, a1, a2, a3, a4, a5, a6) => {
+            values.push(destination_register(destination, state));
+            values.push(source(sysno, state));
+            for arg in [a1, a2, a3, a4, a5, a6] {
+                values.push(match arg {
+                    Some(arg) => source(arg, state),
+                    None => "null".to_string(),
+                });
+            }
+        }
+    }
+    values.join(",")
+}
+
+fn register(state: State, i: usize) -> Option<i64> {
+    state.registers.get(i).copied()
+}
+
+fn or_null(value: Option<impl ToString>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+fn source(source: &Source, state: State) -> String {
+    or_null(state.value(source))
+}
+
+fn destination_register(destination: &Destination, state: State) -> String {
+    let register = match destination {
+        Destination::Address(i) => Some(*i),
+        Destination::Reference(i) => register(state, *i).map(|i| i as usize),
+    };
+    format!("{{\"reg\":{}}}", or_null(register))
+}
+
+fn target(label: &Label) -> String {
+    match label {
+        Label::Instruction(pc) => format!("{{\"target\":{}}}", pc),
+        Label::Label(name) => format!("{{\"label\":{}}}", string(name)),
+    }
+}
+
+/// Quotes and escapes `text` as a JSON string.
+fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
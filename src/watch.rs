@@ -0,0 +1,105 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::range::parse_range;
+
+/// The kind of register access a watchpoint triggers on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Suspends the runner after an instruction reads or writes a register in an
+/// inclusive range, see `RunnerBuilder::watch`.
+///
+/// Parsed from `<registers>[:r|w|rw]`, e.g. `5`, `10..20:rw`, `3..=4:r` or `100..`.
+/// Watchpoints trigger on writes unless told otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize,
+    pub access: Access,
+}
+
+/// The first watched access made by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Index of the instruction that made the access.
+    pub pc: usize,
+    pub register: usize,
+    /// Either `Access::Read` or `Access::Write`.
+    pub access: Access,
+    /// The value before the access, the same as `value` for reads.
+    pub old: i64,
+    pub value: i64,
+}
+
+impl Watchpoint {
+    pub fn new(start: usize, end: usize, access: Access) -> Watchpoint {
+        Watchpoint { start, end, access }
+    }
+
+    pub fn matches(&self, register: usize, access: Access) -> bool {
+        (self.start..=self.end).contains(&register)
+            && (self.access == access || self.access == Access::ReadWrite)
+    }
+}
+
+impl FromStr for Access {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "r" => Ok(Access::Read),
+            "w" => Ok(Access::Write),
+            "rw" => Ok(Access::ReadWrite),
+            _ => Err(format!("Invalid access: {}, expected r, w or rw", s)),
+        }
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (registers, access) = match s.split_once(':') {
+            Some((registers, access)) => (registers, access.parse()?),
+            None => (s, Access::Write),
+        };
+
+        let (start, end) = parse_range(registers, "register")?;
+        Ok(Watchpoint::new(start, end, access))
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)?;
+        } else if self.end == usize::MAX {
+            write!(f, "{}..", self.start)?;
+        } else {
+            write!(f, "{}..={}", self.start, self.end)?;
+        }
+        match self.access {
+            Access::Read => write!(f, ":r"),
+            Access::Write => write!(f, ":w"),
+            Access::ReadWrite => write!(f, ":rw"),
+        }
+    }
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.access {
+            Access::Read => write!(f, "r{} read: {}", self.register, self.value),
+            _ => write!(
+                f,
+                "r{} written: {} -> {}",
+                self.register, self.old, self.value
+            ),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use yaul::{BufferIo, Program, Runner, RuntimeErrorKind};
+
+fn bounds(source: &str) -> Vec<Option<usize>> {
+    Program::from_source(source)
+        .unwrap()
+        .register_bounds()
+        .to_vec()
+}
+
+fn out_of_bounds(kind: &RuntimeErrorKind) -> Option<usize> {
+    match kind {
+        RuntimeErrorKind::RegisterOutOfBounds(register) => Some(*register),
+        _ => None,
+    }
+}
+
+#[test]
+fn direct_operands() {
+    assert_eq!(
+        bounds("set '1' 5\nadd 5 '2' 3\nout 9\ncin 10 '4'"),
+        vec![Some(5), Some(5), Some(9), Some(13)]
+    );
+}
+
+#[test]
+fn references_bounded_by_loops() {
+    let source = "\
+set '10' 1
+LOOP:
+set '7' &1
+add 1 '1' 1
+jlt 1 '20' LOOP
+out &1
+";
+    assert_eq!(
+        bounds(source),
+        vec![Some(1), Some(19), Some(1), Some(1), Some(20)]
+    );
+}
+
+#[test]
+fn references_through_calls() {
+    // READ is called with 30 and then 40 in register 0.
+    let source = "\
+set '30' 0
+call READ
+set '40' 0
+call READ
+out &0
+ret
+READ:
+out &0
+ret
+";
+    assert_eq!(bounds(source)[4], Some(40));
+    assert_eq!(bounds(source)[6], Some(40));
+}
+
+#[test]
+fn unknown_references() {
+    assert_eq!(bounds("in 0\nout &0"), vec![Some(0), None]);
+    assert_eq!(bounds("set '-1' 0\nout &0"), vec![Some(0), None]);
+    // Writing through an unknown reference may change the pointer to 9.
+    assert_eq!(
+        bounds("set '5' 0\nin 1\nset '9' &1\nout &0"),
+        vec![Some(0), Some(1), None, Some(9)]
+    );
+    assert_eq!(bounds("set '5' 0\ncall host:f\nout &0")[2], None);
+}
+
+#[test]
+fn unproven_instructions_are_still_checked() {
+    let program = Program::from_source("set '100' 0\nout &0").unwrap();
+    assert_eq!(program.register_bounds()[1], Some(100));
+
+    let mut runner = Runner::builder()
+        .registers(50)
+        .io(BufferIo::new(""))
+        .build();
+    let error = runner.run(&program).unwrap_err();
+    assert_eq!(out_of_bounds(&error.kind), Some(100));
+}
+
+#[test]
+fn changing_registers_drops_the_proofs() {
+    let program = Program::from_source("set '5' 0\nout &0").unwrap();
+    assert_eq!(program.register_bounds()[1], Some(5));
+
+    let mut runner = Runner::builder()
+        .registers(8)
+        .fuel(1)
+        .io(BufferIo::new(""))
+        .build();
+    runner.run(&program).unwrap();
+    runner.registers_mut()[0] = 1000;
+    runner.set_fuel(None);
+    let error = runner.resume(&program).unwrap_err();
+    assert_eq!(out_of_bounds(&error.kind), Some(1000));
+
+    // Neither does resuming a program where another one was suspended.
+    let other = Program::from_source("set '2000' 0\nset '0' 1").unwrap();
+    runner.set_fuel(Some(1));
+    runner.run(&other).unwrap();
+    runner.set_fuel(None);
+    let error = runner.resume(&program).unwrap_err();
+    assert_eq!(out_of_bounds(&error.kind), Some(2000));
+}
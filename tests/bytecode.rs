@@ -0,0 +1,149 @@
+mod common;
+
+use std::collections::HashMap;
+use std::process::Stdio;
+
+use common::{example, program_output, yaul};
+use yaul::instructions::{Instruction, Label};
+use yaul::source::SourceMap;
+use yaul::{BufferIo, BytecodeError, Program, Runner};
+
+#[test]
+fn round_trips_every_example() {
+    for entry in std::fs::read_dir(example("")).unwrap() {
+        let path = entry.unwrap().path();
+        let program = Program::from_file(&path).unwrap();
+
+        let bytes = program.to_bytecode().unwrap();
+        let loaded = Program::from_bytecode(&bytes, &path).unwrap();
+
+        assert_eq!(
+            loaded.instructions(),
+            program.instructions(),
+            "{}",
+            path.display()
+        );
+        assert_eq!(loaded.labels(), program.labels(), "{}", path.display());
+    }
+}
+
+#[test]
+fn loaded_program_runs() {
+    let program = Program::from_file(example("call")).unwrap();
+    let loaded = Program::from_bytecode(&program.to_bytecode().unwrap(), "call.yaulc").unwrap();
+
+    let mut runner = Runner::builder()
+        .registers(128)
+        .io(BufferIo::new(""))
+        .build();
+    runner.run(&loaded).unwrap();
+    assert_eq!(runner.io().output_string(), "9\n25\n");
+}
+
+#[test]
+fn rejects_invalid_bytecode() {
+    let program = Program::from_file(example("fault")).unwrap();
+    let bytes = program.to_bytecode().unwrap();
+
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[0] = b'X';
+    assert_eq!(
+        Program::from_bytecode(&wrong_magic, "").unwrap_err(),
+        BytecodeError::InvalidMagic
+    );
+
+    let mut wrong_version = bytes.clone();
+    wrong_version[5] = 99;
+    assert_eq!(
+        Program::from_bytecode(&wrong_version, "").unwrap_err(),
+        BytecodeError::UnsupportedVersion(99)
+    );
+
+    let mut corrupted = bytes.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        Program::from_bytecode(&corrupted, ""),
+        Err(BytecodeError::ChecksumMismatch { .. })
+    ));
+
+    assert_eq!(
+        Program::from_bytecode(&bytes[..8], "").unwrap_err(),
+        BytecodeError::Truncated
+    );
+
+    let jump = Program::new(
+        vec![Instruction::Jump(Label::Instruction(5))],
+        HashMap::new(),
+        SourceMap::new("".into()),
+    );
+    assert_eq!(
+        Program::from_bytecode(&jump.to_bytecode().unwrap(), "").unwrap_err(),
+        BytecodeError::InvalidTarget(5)
+    );
+    let label = Program::new(
+        vec![Instruction::Return],
+        HashMap::from([("END".to_string(), 2)]),
+        SourceMap::new("".into()),
+    );
+    assert_eq!(
+        Program::from_bytecode(&label.to_bytecode().unwrap(), "").unwrap_err(),
+        BytecodeError::InvalidTarget(2)
+    );
+}
+
+#[test]
+fn rejects_values_too_large_to_encode() {
+    let target = u32::MAX as usize + 1;
+    let jump = Program::new(
+        vec![Instruction::Jump(Label::Instruction(target))],
+        HashMap::new(),
+        SourceMap::new("".into()),
+    );
+    assert_eq!(
+        jump.to_bytecode().unwrap_err(),
+        BytecodeError::TooLarge(target)
+    );
+}
+
+#[test]
+fn text_starting_like_bytecode_is_parsed() {
+    let bytes = Program::from_file(example("call"))
+        .unwrap()
+        .to_bytecode()
+        .unwrap();
+    assert!(yaul::bytecode::is_bytecode(&bytes));
+    assert!(!yaul::bytecode::is_bytecode(b"YAULC:\nset '1' 0\nout 0\n"));
+
+    let path = std::env::temp_dir().join(format!("yaul-magic-{}", std::process::id()));
+    std::fs::write(&path, "YAULC:\nset '1' 0\nout 0\n").unwrap();
+    let output = yaul(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(program_output(&output.stdout), vec!["1"]);
+}
+
+#[test]
+fn compile_subcommand() {
+    let path = std::env::temp_dir().join(format!("yaul-compile-{}.yaulc", std::process::id()));
+
+    let compiled = std::process::Command::new(env!("CARGO_BIN_EXE_yaul"))
+        .arg("compile")
+        .arg(example("call"))
+        .arg("--output")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(compiled.status.success());
+
+    let from_source = yaul(example("call")).output().unwrap();
+    let from_bytecode = yaul(&path).stdin(Stdio::null()).output().unwrap();
+    assert!(from_bytecode.status.success());
+    assert!(String::from_utf8_lossy(&from_bytecode.stdout).starts_with("Loaded in"));
+    assert_eq!(
+        program_output(&from_bytecode.stdout),
+        program_output(&from_source.stdout)
+    );
+
+    std::fs::remove_file(path).unwrap();
+}
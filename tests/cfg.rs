@@ -0,0 +1,100 @@
+mod common;
+
+use common::example;
+use yaul::{Cfg, Loop, Program};
+
+fn sort() -> (Program, Cfg) {
+    let program = Program::from_file(example("sort")).unwrap();
+    let cfg = Cfg::new(&program);
+    (program, cfg)
+}
+
+#[test]
+fn splits_blocks_at_labels_and_control_flow() {
+    let (program, cfg) = sort();
+    let blocks = cfg.blocks();
+
+    assert_eq!(blocks.len(), 17);
+    assert_eq!(blocks[0].instructions(), 0..12);
+    // `call` ends a block and has edges to the called block and the next one.
+    assert_eq!(blocks[0].successors, vec![14, 1]);
+    assert_eq!(blocks[14].predecessors, vec![0, 2]);
+
+    let sort_loop = program.labels()["SORT_ARRAY_LOOP"];
+    assert_eq!(cfg.block_of(sort_loop), Some(5));
+    assert_eq!(blocks[5].successors, vec![10, 6]);
+    assert_eq!(blocks[5].predecessors, vec![4, 9, 11]);
+    // `ret` has no successors.
+    assert!(blocks[3].successors.is_empty());
+    assert_eq!(cfg.block_of(program.instructions().len()), None);
+}
+
+#[test]
+fn dominators() {
+    let (_, cfg) = sort();
+
+    assert_eq!(cfg.immediate_dominator(0), None);
+    assert_eq!(cfg.immediate_dominator(5), Some(4));
+    // Reached both from the swap and from skipping it.
+    assert_eq!(cfg.immediate_dominator(9), Some(6));
+    assert_eq!(cfg.immediate_dominator(14), Some(0));
+    assert!(cfg.dominates(4, 11));
+    assert!(cfg.dominates(11, 11));
+    assert!(!cfg.dominates(8, 9));
+}
+
+#[test]
+fn natural_loops() {
+    let (_, cfg) = sort();
+
+    assert_eq!(
+        cfg.loops(),
+        vec![
+            Loop {
+                header: 5,
+                latches: vec![9, 11],
+                blocks: vec![5, 6, 7, 8, 9, 10, 11],
+            },
+            Loop {
+                header: 15,
+                latches: vec![15],
+                blocks: vec![15],
+            },
+        ]
+    );
+}
+
+#[test]
+fn unreachable_blocks() {
+    let program = Program::from_source("jmp END\nout 1\nunused:\nout 2\nEND:\nret").unwrap();
+
+    let cfg = Cfg::new(&program);
+    assert_eq!(cfg.blocks().len(), 4);
+    assert!(cfg.is_reachable(0));
+    assert!(!cfg.is_reachable(1));
+    assert!(!cfg.is_reachable(2));
+    assert!(cfg.is_reachable(3));
+    assert_eq!(cfg.immediate_dominator(2), None);
+    assert!(cfg.loops().is_empty());
+
+    // Without labels only jump targets start blocks.
+    let cfg = Cfg::from_instructions(program.instructions());
+    assert_eq!(cfg.blocks().len(), 3);
+    assert_eq!(cfg.blocks()[1].instructions(), 1..3);
+}
+
+#[test]
+fn dot_export() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_yaul"))
+        .arg("cfg")
+        .arg(example("call"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let dot = String::from_utf8(output.stdout).unwrap();
+    assert!(dot.starts_with("digraph \""));
+    assert!(dot.contains("  b3 [label=\"SQUARE:\\l9: mul &100 &100 &100\\l10: ret\\l\"];\n"));
+    assert!(dot.contains("  b0 -> b3 [style=dashed];\n  b0 -> b1;\n"));
+    assert!(dot.ends_with("}\n"));
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use std::process::{Command, Output};
+
+use common::example;
+use yaul::{check, Lint, LintKind, Program};
+
+fn lints(source: &str) -> Vec<(usize, LintKind)> {
+    check(&Program::from_source(source).unwrap())
+        .into_iter()
+        .map(|lint| (lint.pc, lint.kind))
+        .collect()
+}
+
+#[test]
+fn reads_before_writes() {
+    let source = "\
+in 0
+jlt 0 '0' SKIP
+set '1' 1
+SKIP:
+out 1
+out 0
+";
+    assert_eq!(lints(source), vec![(3, LintKind::ReadBeforeWrite(1))]);
+}
+
+#[test]
+fn calls_write_for_the_caller() {
+    // INIT always writes 1 and sometimes 2.
+    let source = "\
+call INIT
+out 1
+out 2
+ret
+INIT:
+set '1' 1
+in 0
+jlt 0 '0' DONE
+set '2' 2
+DONE:
+ret
+";
+    assert_eq!(lints(source), vec![(2, LintKind::ReadBeforeWrite(2))]);
+}
+
+#[test]
+fn unread_writes() {
+    let source = "\
+set '1' 0
+set '2' 0
+set '3' 1
+out 0
+ret
+";
+    assert_eq!(
+        lints(source),
+        vec![(0, LintKind::NeverRead(0)), (2, LintKind::NeverRead(1))]
+    );
+}
+
+#[test]
+fn references_may_access_anything() {
+    let source = "\
+set '10' 0
+set '5' &0
+set '7' 10
+out &0
+";
+    assert!(lints(source).is_empty());
+}
+
+#[test]
+fn unreachable_code() {
+    let source = "jmp END\nout 1\nunused:\nout 2\nEND:\nret\nout 3\n";
+    assert_eq!(
+        check(&Program::from_source(source).unwrap()),
+        vec![
+            Lint {
+                pc: 1,
+                operand: None,
+                kind: LintKind::Unreachable(2)
+            },
+            Lint {
+                pc: 4,
+                operand: None,
+                kind: LintKind::Unreachable(1)
+            },
+        ]
+    );
+}
+
+fn yaul_check(name: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_yaul"))
+        .arg("check")
+        .arg(example(name))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn check_command() {
+    let output = yaul_check("collatz");
+    assert!(output.status.success());
+
+    // A negative number skips straight to printing the result.
+    let output = yaul_check("sqrt");
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("warning: Register 4 may be read before it is written\n"));
+    assert!(stdout.contains("sqrt:30:5\n"));
+    assert!(stdout.ends_with("1 warning(s)\n"));
+}
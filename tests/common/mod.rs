@@ -0,0 +1,34 @@
+#![allow(dead_code)] // Not every test crate uses every helper
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Returns the path of a test fixture in `tests/programs/`.
+pub fn program(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("programs")
+        .join(name)
+}
+
+/// Returns the path of one of the example programs shipped in `programs/`.
+pub fn example(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("programs")
+        .join(name)
+}
+
+pub fn yaul(file: impl AsRef<Path>) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_yaul"));
+    command.arg(file.as_ref());
+    command
+}
+
+/// Returns the lines printed by the program, skipping the compile or load time header.
+pub fn program_output(stdout: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter(|line| !line.starts_with("Compiled in") && !line.starts_with("Loaded in"))
+        .map(|line| line.to_string())
+        .collect()
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use std::io::Write;
+use std::process::Stdio;
+
+use common::{example, yaul};
+use yaul::{BranchCoverage, BufferIo, Coverage, LineCoverage, Program, Runner};
+
+const SOURCE: &str = "\
+set '3' 0
+LOOP:
+sub 0 '1' 0
+jgt 0 '0' LOOP
+jeq 0 '1' NEVER
+ret
+NEVER:
+out 0
+";
+
+fn coverage(program: &Program) -> Coverage<'_> {
+    let mut runner = Runner::builder()
+        .registers(4)
+        .io(BufferIo::new(""))
+        .observer(Coverage::new(program))
+        .build();
+    runner.run(program).unwrap();
+    runner.into_observer()
+}
+
+#[test]
+fn records_lines_and_branches() {
+    let program = Program::from_source(SOURCE).unwrap();
+    let coverage = coverage(&program);
+
+    let line = |line, count| LineCoverage { line, count };
+    assert_eq!(
+        coverage.lines(),
+        [
+            line(1, 1),
+            line(3, 3),
+            line(4, 3),
+            line(5, 1),
+            line(6, 1),
+            line(8, 0)
+        ]
+    );
+    assert_eq!(
+        coverage.branches(),
+        [
+            BranchCoverage {
+                pc: 2,
+                line: 4,
+                taken: 2,
+                not_taken: 1,
+            },
+            BranchCoverage {
+                pc: 3,
+                line: 5,
+                taken: 0,
+                not_taken: 1,
+            },
+        ]
+    );
+
+    let summary = coverage.summary();
+    assert!(summary.contains("Lines:    5/6 (83.3%)"));
+    assert!(summary.contains("Branches: 1/2 (50.0%)"));
+    assert!(summary.contains("     8 | out 0"));
+    assert!(summary.contains("     5 | jeq 0 '1' NEVER  (never jumped)"));
+}
+
+#[test]
+fn writes_lcov() {
+    let program = Program::from_source(SOURCE).unwrap();
+    let mut lcov = Vec::new();
+    coverage(&program).write_lcov(&mut lcov).unwrap();
+
+    assert_eq!(
+        String::from_utf8(lcov).unwrap(),
+        "\
+TN:
+SF:<source>
+BRDA:4,2,0,2
+BRDA:4,2,1,1
+BRDA:5,3,0,0
+BRDA:5,3,1,1
+BRF:4
+BRH:3
+DA:1,1
+DA:3,3
+DA:4,3
+DA:5,1
+DA:6,1
+DA:8,0
+LF:6
+LH:5
+end_of_record
+"
+    );
+}
+
+#[test]
+fn coverage_flags() {
+    let lcov = std::env::temp_dir().join(format!("yaul-coverage-{}.info", std::process::id()));
+    let path = example("count");
+    let mut child = yaul(&path)
+        .arg("--coverage")
+        .arg("--lcov")
+        .arg(&lcov)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"3\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success());
+    assert!(stderr.contains("Coverage of"));
+    assert!(stderr.contains("Branches: 1/1 (100.0%)"));
+
+    let lcov_contents = std::fs::read_to_string(&lcov).unwrap();
+    assert!(lcov_contents.starts_with(&format!("TN:\nSF:{}\n", path.display())));
+    std::fs::remove_file(lcov).unwrap();
+}
+
+#[test]
+fn bytecode_has_no_lines() {
+    let program = Program::from_source(SOURCE).unwrap();
+    let loaded = Program::from_bytecode(&program.to_bytecode().unwrap(), "loop.yaulc").unwrap();
+    let coverage = coverage(&loaded);
+    assert!(coverage.lines().is_empty());
+    assert!(coverage.branches().is_empty());
+
+    let mut lcov = Vec::new();
+    coverage.write_lcov(&mut lcov).unwrap();
+    let lcov = String::from_utf8(lcov).unwrap();
+    assert!(!lcov.contains("DA:"));
+    assert!(lcov.contains("LF:0\n"));
+
+    let path = std::env::temp_dir().join(format!("yaul-coverage-{}.yaulc", std::process::id()));
+    std::fs::write(&path, program.to_bytecode().unwrap()).unwrap();
+    let output = yaul(&path)
+        .arg("--lcov")
+        .arg("unused.info")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("bytecode has no source lines"));
+}
@@ -0,0 +1,240 @@
+mod common;
+
+use std::io::Write;
+use std::process::Stdio;
+
+use common::{example, yaul};
+use yaul::debugger::{Command, Location, Register, Registers};
+use yaul::{BufferIo, Debugger, ExitStatus, Program, Runner, Stop};
+
+fn runner() -> Runner<BufferIo> {
+    Runner::builder()
+        .registers(128)
+        .io(BufferIo::new(""))
+        .build()
+}
+
+#[test]
+fn breakpoints_and_finish() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut debugger = Debugger::new(&program, runner());
+
+    let pc = debugger
+        .add_breakpoint(&Location::Label("SQUARE".to_string()))
+        .unwrap();
+    assert_eq!(pc, 9);
+
+    assert!(matches!(debugger.resume(), Ok(Stop::Breakpoint(9))));
+    assert_eq!(debugger.runner().stack(), &[4]);
+
+    assert!(matches!(debugger.step_out(), Ok(Stop::Step)));
+    assert_eq!(debugger.runner().pc(), 4);
+    assert_eq!(debugger.read(0).unwrap(), 9);
+
+    assert!(matches!(debugger.resume(), Ok(Stop::Breakpoint(9))));
+    assert!(matches!(
+        debugger.resume(),
+        Ok(Stop::Exited(ExitStatus::Returned))
+    ));
+    assert!(debugger.step().is_err());
+
+    debugger.restart();
+    assert_eq!(debugger.runner().pc(), 0);
+    assert_eq!(debugger.read(0).unwrap(), 0);
+}
+
+#[test]
+fn next_steps_over_calls() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut debugger = Debugger::new(&program, runner());
+
+    for _ in 0..3 {
+        debugger.step().unwrap();
+    }
+    assert_eq!(debugger.runner().pc(), 3);
+
+    debugger.step_over().unwrap();
+    assert_eq!(debugger.runner().pc(), 4);
+    assert_eq!(debugger.runner().stack(), &[] as &[usize]);
+    assert_eq!(debugger.read(0).unwrap(), 9);
+}
+
+#[test]
+fn breakpoint_on_line() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut debugger = Debugger::new(&program, runner());
+
+    // Line 6 is blank, the breakpoint moves to `set '0' 100` on line 7.
+    assert_eq!(debugger.add_breakpoint(&Location::Line(6)), Ok(2));
+    assert!(debugger.add_breakpoint(&Location::Line(100)).is_err());
+    assert!(debugger
+        .add_breakpoint(&Location::Label("NOPE".to_string()))
+        .is_err());
+}
+
+#[test]
+fn print_and_set_registers() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut debugger = Debugger::new(&program, runner());
+
+    debugger.execute(&Command::Set(Register::Address(0), 7));
+    debugger.execute(&Command::Set(Register::Reference(0), 42));
+    assert_eq!(debugger.read(7).unwrap(), 42);
+
+    assert_eq!(
+        debugger.execute(&Command::Print(Registers::Range(6, 7))),
+        "r6 = 0\nr7 = 42"
+    );
+    assert_eq!(
+        debugger.execute(&Command::Print(Registers::Deref(0))),
+        "&0 -> r7 = 42"
+    );
+    assert!(debugger
+        .execute(&Command::Print(Registers::Range(126, usize::MAX)))
+        .ends_with("r127 = 0"));
+    assert!(debugger
+        .execute(&Command::Print(Registers::Deref(1000)))
+        .starts_with("Error: Register out of bounds"));
+}
+
+#[test]
+fn watchpoints_stop_execution() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut debugger = Debugger::new(&program, runner());
+
+    debugger.execute(&Command::Watch("0".parse().unwrap()));
+    assert!(matches!(debugger.resume(), Ok(Stop::Watchpoint(hit)) if hit.pc == 0));
+    assert!(matches!(debugger.resume(), Ok(Stop::Watchpoint(hit)) if hit.pc == 9));
+    assert_eq!(debugger.runner().pc(), 10);
+}
+
+#[test]
+fn reverse_execution() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut debugger = Debugger::new(&program, runner());
+
+    assert!(matches!(
+        debugger.resume(),
+        Ok(Stop::Exited(ExitStatus::Returned))
+    ));
+    assert_eq!(debugger.executed(), 13);
+
+    debugger.execute(&Command::Watch("0".parse().unwrap()));
+    assert!(matches!(
+        debugger.reverse_continue(),
+        Ok(Stop::Watchpoint(hit)) if hit.pc == 9 && hit.old == 3 && hit.value == 9
+    ));
+    assert_eq!(debugger.runner().pc(), 9);
+    assert_eq!(debugger.read(0).unwrap(), 3);
+    assert_eq!(debugger.runner().stack(), &[4]);
+
+    assert!(matches!(debugger.reverse_continue(), Ok(Stop::Watchpoint(hit)) if hit.pc == 0));
+    assert!(debugger.reverse_step().is_err());
+
+    assert!(matches!(debugger.goto(10), Ok(Stop::Step)));
+    assert_eq!(debugger.runner().pc(), 6);
+    assert_eq!(debugger.read(1).unwrap(), 25);
+    debugger.goto(2).unwrap();
+    assert_eq!(debugger.runner().pc(), 2);
+    assert_eq!(debugger.read(1).unwrap(), 5);
+
+    debugger.execute(&Command::Unwatch("0".parse().unwrap()));
+    assert!(matches!(debugger.reverse_continue(), Ok(Stop::Start)));
+}
+
+#[test]
+fn reverse_step_undoes_set() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut debugger = Debugger::new(&program, runner());
+
+    debugger.step().unwrap();
+    debugger.execute(&Command::Set(Register::Address(0), 7));
+    debugger.execute(&Command::Set(Register::Address(50), 8));
+    debugger.step().unwrap();
+    debugger.execute(&Command::Set(Register::Address(1), 9));
+
+    debugger.reverse_step().unwrap();
+    assert_eq!(debugger.read(0).unwrap(), 7);
+    assert_eq!(debugger.read(1).unwrap(), 0);
+    assert_eq!(debugger.read(50).unwrap(), 8);
+    debugger.reverse_step().unwrap();
+    assert_eq!(debugger.read(0).unwrap(), 0);
+    assert_eq!(debugger.read(50).unwrap(), 0);
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(
+        "b SQUARE".parse(),
+        Ok(Command::Break(Location::Label("SQUARE".to_string())))
+    );
+    assert_eq!("break 12".parse(), Ok(Command::Break(Location::Line(12))));
+    assert_eq!("p 3..5".parse(), Ok(Command::Print(Registers::Range(3, 4))));
+    assert_eq!(
+        "p 3..=5".parse(),
+        Ok(Command::Print(Registers::Range(3, 5)))
+    );
+    assert_eq!("p ..2".parse(), Ok(Command::Print(Registers::Range(0, 1))));
+    assert_eq!(
+        "p 3..".parse(),
+        Ok(Command::Print(Registers::Range(3, usize::MAX)))
+    );
+    assert_eq!("p &2".parse(), Ok(Command::Print(Registers::Deref(2))));
+    assert_eq!(
+        "set &1 -4".parse(),
+        Ok(Command::Set(Register::Reference(1), -4))
+    );
+    assert!("set 1".parse::<Command>().is_err());
+    assert!("jump".parse::<Command>().is_err());
+}
+
+#[test]
+fn debug_subcommand() {
+    let mut child = yaul("debug")
+        .arg(example("call"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"break SQUARE\ncontinue\nprint 0\nbt\nquit\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Breakpoint hit at instruction 9"));
+    assert!(stdout.contains("r0 = 3"));
+    assert!(stdout.contains("returns to instruction 4"));
+}
+
+#[test]
+fn goto_reuses_input() {
+    let program = Program::from_source("in 0\ntime 1\nin 2\nout 0").unwrap();
+    let mut debugger = Debugger::new(
+        &program,
+        Runner::builder()
+            .registers(4)
+            .io(BufferIo::new("5\n7\n"))
+            .record()
+            .build(),
+    );
+
+    assert!(matches!(
+        debugger.resume(),
+        Ok(Stop::Exited(ExitStatus::Completed))
+    ));
+    let registers = debugger.runner().registers().to_vec();
+
+    // The input is used up, so reading it again would fail.
+    debugger.goto(1).unwrap();
+    assert!(matches!(
+        debugger.goto(4),
+        Ok(Stop::Exited(ExitStatus::Completed))
+    ));
+    assert_eq!(debugger.runner().registers(), registers);
+    assert_eq!(debugger.runner().recording().unwrap().events.len(), 3);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::{example, program_output, yaul};
+use yaul::instructions::{Destination, Instruction, Label, Source};
+use yaul::source::SourceMap;
+use yaul::{disassemble, Program};
+
+#[test]
+fn round_trips_every_example() {
+    for entry in std::fs::read_dir(example("")).unwrap() {
+        let path = entry.unwrap().path();
+        let program = Program::from_file(&path).unwrap();
+
+        let text = disassemble(&program);
+        let reparsed = Program::from_source(&text)
+            .unwrap_or_else(|e| panic!("{}: {}\n{}", path.display(), e, text));
+
+        assert_eq!(
+            reparsed.instructions(),
+            program.instructions(),
+            "{}",
+            path.display()
+        );
+        assert_eq!(disassemble(&reparsed), text, "{}", path.display());
+    }
+}
+
+#[test]
+fn synthesizes_labels_for_targets() {
+    let program = Program::new(
+        vec![
+            Instruction::JumpEqual(Source::Address(0), Source::Data(0), Label::Instruction(2)),
+            Instruction::Output(Source::Reference(1)),
+            Instruction::Syscall(
+                Destination::Address(3),
+                Source::Data(1),
+                None,
+                Some(Source::Data(-4)),
+                None,
+                None,
+                None,
+                None,
+            ),
+            Instruction::Jump(Label::Instruction(4)),
+        ],
+        [("L2".to_string(), 0)].into_iter().collect(),
+        SourceMap::new("<bytecode>".into()),
+    );
+
+    assert_eq!(
+        disassemble(&program),
+        "jeq 0 '0' L2_\nout &1\n\nL2_:\nsys 3 '1' _ '-4'\njmp L4\n\nL4:\n"
+    );
+}
+
+#[test]
+fn disasm_subcommand() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_yaul"))
+        .arg("disasm")
+        .arg(example("call"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "set '3' 0\nset '5' 1\nset '0' 100\ncall SQUARE\nset '1' 100\ncall SQUARE\nout 0\nout 1\nret\n\nSQUARE:\nmul &100 &100 &100\nret\n"
+    );
+
+    // The printed program runs like the original.
+    let path = std::env::temp_dir().join(format!("yaul-disasm-{}", std::process::id()));
+    std::fs::write(&path, &output.stdout).unwrap();
+    let rerun = yaul(&path).output().unwrap();
+    assert!(rerun.status.success());
+    assert_eq!(
+        program_output(&rerun.stdout),
+        vec!["9".to_string(), "25".to_string()]
+    );
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use common::example;
+use yaul::{BufferIo, ExitStatus, Parser, Program, Runner, RuntimeErrorKind, Trap};
+
+#[test]
+fn runs_program_from_source() {
+    let source = "\
+set '3' 0
+call SQUARE
+ret
+
+SQUARE:
+mul 0 0 1
+ret
+";
+    let program = Program::from_source(source).unwrap();
+
+    let mut runner = Runner::builder().registers(4).build();
+    let status = runner.run(&program).unwrap();
+
+    assert_eq!(status, ExitStatus::Returned);
+    assert_eq!(runner.registers(), &[3, 9, 0, 0]);
+    assert_eq!(program.labels()["SQUARE"], 3);
+}
+
+#[test]
+fn parse_errors_are_returned() {
+    let mut parser = Parser::from_source("broken", "set '1'\njmp NOWHERE");
+    let error = parser.parse().unwrap_err();
+
+    assert_eq!(error.span().map(|span| span.line), Some(1));
+    assert_eq!(parser.diagnostics().len(), 2);
+}
+
+#[test]
+fn runtime_errors_are_returned() {
+    let program = Program::from_source("set '1' 7").unwrap();
+
+    let mut runner = Runner::builder().registers(4).build();
+    let error = runner.run(&program).unwrap_err();
+
+    assert!(matches!(
+        error.kind,
+        RuntimeErrorKind::RegisterOutOfBounds(7)
+    ));
+    assert_eq!(error.pc, 0);
+}
+
+#[test]
+fn io_can_be_scripted_and_captured() {
+    let program = Program::from_file(example("fibonacci")).unwrap();
+
+    let mut runner = Runner::builder().io(BufferIo::new("7\n")).build();
+    runner.run(&program).unwrap();
+
+    assert_eq!(runner.io().output_string(), "0\n1\n1\n2\n3\n5\n8\n");
+}
+
+#[test]
+fn char_io_can_be_scripted_and_captured() {
+    let program = Program::from_file(example("cin")).unwrap();
+
+    let mut runner = Runner::builder().io(BufferIo::new("hello\n")).build();
+    runner.run(&program).unwrap();
+
+    assert_eq!(runner.io().output_string(), "hello");
+}
+
+#[test]
+fn invalid_input_is_an_error() {
+    let program = Program::from_source("in 0").unwrap();
+
+    let mut runner = Runner::builder().io(BufferIo::new("abc\n")).build();
+    let error = runner.run(&program).unwrap_err();
+
+    assert!(matches!(error.kind, RuntimeErrorKind::InvalidInput(ref input) if input == "abc"));
+}
+
+#[test]
+fn host_functions_can_be_called() {
+    let program = Program::from_source("set '49' 0\ncall host:sqrt\nout 1").unwrap();
+
+    let mut runner = Runner::builder()
+        .io(BufferIo::default())
+        .host_function("sqrt", |registers| {
+            if registers[0] < 0 {
+                return Err(Trap::new("negative input"));
+            }
+            registers[1] = (registers[0] as f64).sqrt() as i64;
+            Ok(())
+        })
+        .build();
+    runner.run(&program).unwrap();
+
+    assert_eq!(runner.io().output_string(), "7\n");
+}
+
+#[test]
+fn host_function_traps_stop_the_program() {
+    let program = Program::from_source("call host:fail\nout '1'").unwrap();
+
+    let mut runner = Runner::builder().io(BufferIo::default()).build();
+    runner.register_host_function("fail", |_| Err(Trap::new("nope")));
+    let error = runner.run(&program).unwrap_err();
+
+    assert!(matches!(
+        error.kind,
+        RuntimeErrorKind::HostTrap { ref function, ref message } if function == "fail" && message == "nope"
+    ));
+    assert!(runner.io().output().is_empty());
+}
+
+#[test]
+fn unknown_host_functions_are_an_error() {
+    let program = Program::from_source("call host:missing").unwrap();
+
+    let error = Runner::new(1).run(&program).unwrap_err();
+
+    assert!(
+        matches!(error.kind, RuntimeErrorKind::UnknownHostFunction(ref name) if name == "missing")
+    );
+}
+
+#[test]
+fn out_of_fuel_suspends_and_resumes() {
+    let source = "\
+set '0' 0
+LOOP:
+add 0 '1' 0
+call NOOP
+jmp LOOP
+
+NOOP:
+ret
+";
+    let program = Program::from_source(source).unwrap();
+
+    let mut runner = Runner::builder().registers(1).fuel(11).build();
+    assert_eq!(runner.run(&program).unwrap(), ExitStatus::OutOfFuel);
+
+    // set, then (add, call, ret, jmp) twice, then add and call
+    assert_eq!(runner.fuel(), Some(0));
+    assert_eq!(runner.registers(), &[3]);
+    assert_eq!(runner.pc(), program.labels()["NOOP"]);
+    assert_eq!(runner.stack(), &[3]);
+
+    runner.add_fuel(4);
+    assert_eq!(runner.resume(&program).unwrap(), ExitStatus::OutOfFuel);
+    assert_eq!(runner.registers(), &[4]);
+    assert_eq!(runner.pc(), program.labels()["NOOP"]);
+}
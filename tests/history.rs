@@ -0,0 +1,133 @@
+mod common;
+
+use common::example;
+use yaul::{BufferIo, ExitStatus, Observer, Program, Runner, StackChange};
+
+#[test]
+fn steps_back_to_the_start() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut runner = Runner::builder()
+        .registers(128)
+        .io(BufferIo::new(""))
+        .history()
+        .build();
+
+    // Stop inside the first call to SQUARE, after `mul`.
+    runner.set_fuel(Some(5));
+    assert_eq!(runner.run(&program).unwrap(), ExitStatus::OutOfFuel);
+    let history = runner.history().unwrap();
+    assert_eq!(history.len(), 5);
+    assert_eq!(history.last().unwrap().writes, &[(0, 3)]);
+    assert_eq!(runner.registers()[0], 9);
+
+    assert_eq!(runner.step_back(), Some(9));
+    assert_eq!(runner.registers()[0], 3);
+    assert_eq!(runner.pc(), 9);
+    assert_eq!(runner.fuel(), Some(1));
+
+    let call = runner.history().unwrap().last().unwrap();
+    assert_eq!(call.stack, StackChange::Pushed);
+    assert_eq!(runner.step_back(), Some(3));
+    assert_eq!(runner.stack(), &[] as &[usize]);
+
+    while runner.step_back().is_some() {}
+    assert_eq!(runner.pc(), 0);
+    assert!(runner.registers().iter().all(|register| *register == 0));
+}
+
+#[test]
+fn undoes_returns() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut runner = Runner::builder()
+        .registers(128)
+        .io(BufferIo::new(""))
+        .history()
+        .build();
+    runner.run(&program).unwrap();
+
+    // Back over `ret` from main, `out 1`, `out 0` and the `ret` from SQUARE.
+    for _ in 0..4 {
+        runner.step_back();
+    }
+    assert_eq!(runner.pc(), 10);
+    assert_eq!(runner.stack(), &[6]);
+
+    runner.set_history(false);
+    assert_eq!(runner.step_back(), None);
+}
+
+#[test]
+fn undoes_host_function_writes() {
+    let program = Program::from_source("set '5' 0\ncall host:bump\nout 0").unwrap();
+    let mut runner = Runner::builder()
+        .registers(4)
+        .io(BufferIo::new(""))
+        .host_function("bump", |registers| {
+            registers[0] += 100;
+            registers[3] = 7;
+            Ok(())
+        })
+        .history()
+        .build();
+
+    runner.set_fuel(Some(2));
+    assert_eq!(runner.run(&program).unwrap(), ExitStatus::OutOfFuel);
+    assert_eq!(runner.registers(), &[105, 0, 0, 7]);
+    assert_eq!(
+        runner.history().unwrap().last().unwrap().writes,
+        &[(0, 5), (3, 0)]
+    );
+
+    assert_eq!(runner.step_back(), Some(1));
+    assert_eq!(runner.registers(), &[5, 0, 0, 0]);
+}
+
+#[test]
+fn host_calls_agree_with_and_without_tracking() {
+    /// Counts the writes it is told about.
+    struct Writes {
+        count: usize,
+        observes: bool,
+    }
+
+    impl Observer for Writes {
+        fn register_written(&mut self, _register: usize, _old: i64, _new: i64) {
+            self.count += 1;
+        }
+
+        fn observes_writes(&self) -> bool {
+            self.observes
+        }
+    }
+
+    let program = Program::from_source("set '5' 0\ncall host:bump\nout 0\nout 3").unwrap();
+    let run = |history: bool, observes: bool| {
+        let mut builder = Runner::builder()
+            .registers(4)
+            .io(BufferIo::new(""))
+            .host_function("bump", |registers| {
+                registers[0] += 100;
+                registers[3] = 7;
+                Ok(())
+            })
+            .observer(Writes { count: 0, observes });
+        if history {
+            builder = builder.history();
+        }
+        let mut runner = builder.build();
+        runner.run(&program).unwrap();
+        (
+            runner.registers().to_vec(),
+            runner.io().output_string(),
+            runner.into_observer().count,
+        )
+    };
+
+    let untracked = run(false, false);
+    assert_eq!(untracked, (vec![105, 0, 0, 7], "105\n7\n".to_string(), 1));
+    assert_eq!(run(false, true).2, 3);
+    assert_eq!(
+        run(true, false),
+        (untracked.0.clone(), untracked.1.clone(), 3)
+    );
+}
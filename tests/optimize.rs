@@ -0,0 +1,189 @@
+mod common;
+
+use std::path::Path;
+
+use common::{example, program, program_output, yaul};
+use yaul::{disassemble, optimize, BufferIo, Event, Pass, Passes, Program, Recording, Runner};
+
+/// Examples that finish quickly and only talk to the outside world through `Io`.
+const EXAMPLES: &[&str] = &[
+    "array",
+    "binarysearch",
+    "call",
+    "cin",
+    "collatz",
+    "count",
+    "fault",
+    "fibonacci",
+    "helloworld",
+    "prime",
+    "sort",
+    "sqrt",
+];
+
+const INPUT: &str = "12\n7\n3\n";
+
+fn configurations() -> Vec<Passes> {
+    let mut configurations = vec![Passes::default()];
+    for pass in [Pass::JumpThreading, Pass::DeadStores, Pass::ConstantFolding] {
+        let mut passes = Passes::none();
+        passes.set(pass, true);
+        configurations.push(passes);
+    }
+    configurations
+}
+
+/// A runner that records what the program reads. Collatz starts from the time
+/// and never finishes from some of them, so it always gets 27 instead.
+fn recorder(path: &Path) -> Runner<BufferIo> {
+    let builder = Runner::builder().io(BufferIo::new(INPUT));
+    if path.ends_with("collatz") {
+        let seed = Recording {
+            events: vec![Event::Time(27)],
+        };
+        builder.replay(seed).build()
+    } else {
+        builder.record().build()
+    }
+}
+
+/// Runs the program and describes everything it did that can be seen from outside.
+fn outcome(program: &Program, runner: &mut Runner<BufferIo>) -> String {
+    let result = match runner.run(program) {
+        Ok(status) => format!("{:?}", status),
+        Err(e) => e.kind.to_string(),
+    };
+    format!("{}\n{}", result, runner.io().output_string())
+}
+
+#[test]
+fn optimized_programs_behave_the_same() {
+    let paths = EXAMPLES
+        .iter()
+        .map(|name| example(name))
+        .chain([program("peephole")]);
+    for path in paths {
+        let program = Program::from_file(&path).unwrap();
+
+        let mut recorder = recorder(&path);
+        let expected = outcome(&program, &mut recorder);
+        let recording = recorder.recording().unwrap().clone();
+
+        for passes in configurations() {
+            let optimized = optimize(&program, passes);
+            // Replaying makes `time` return the same values and fails if the
+            // optimized program asks for input in a different order.
+            let mut runner = Runner::builder()
+                .io(BufferIo::new(INPUT))
+                .replay(recording.clone())
+                .build();
+            assert_eq!(
+                outcome(&optimized, &mut runner),
+                expected,
+                "{} with {:?}",
+                path.display(),
+                passes
+            );
+        }
+    }
+}
+
+#[test]
+fn fixture_shrinks() {
+    let program = Program::from_file(program("peephole")).unwrap();
+    let optimized = optimize(&program, Passes::default());
+
+    assert_eq!(program.instructions().len(), 23);
+    assert_eq!(optimized.instructions().len(), 19);
+}
+
+fn optimized(source: &str, pass: Pass) -> String {
+    let mut passes = Passes::none();
+    passes.set(pass, true);
+    disassemble(&optimize(&Program::from_source(source).unwrap(), passes))
+}
+
+#[test]
+fn folds_constants() {
+    let source = "\
+add '2' '3' 0
+jgt '1' '2' END
+jeq '1' '1' END
+div '1' '0' 0
+END:
+out 0
+";
+    assert_eq!(
+        optimized(source, Pass::ConstantFolding),
+        "set '5' 0\njmp END\ndiv '1' '0' 0\n\nEND:\nout 0\n"
+    );
+}
+
+#[test]
+fn threads_jumps() {
+    let source = "\
+jmp A
+out 1
+A:
+jmp B
+out 2
+B:
+jmp C
+C:
+out 3
+";
+    assert_eq!(
+        optimized(source, Pass::JumpThreading),
+        "jmp B\nout 1\njmp B\nout 2\n\nB:\nout 3\n"
+    );
+
+    // A loop of jumps stays a loop.
+    let source = "LOOP:\njmp AGAIN\nAGAIN:\njmp LOOP\n";
+    assert_eq!(
+        optimized(source, Pass::JumpThreading),
+        "AGAIN:\njmp AGAIN\n"
+    );
+}
+
+#[test]
+fn removes_dead_stores() {
+    let source = "\
+set '1' 0
+add 0 '1' 1
+set '9' 3
+set '8' 3
+set '5' 2
+set &2 2
+call F
+set '7' 4
+ret
+F:
+set '6' 4
+ret
+";
+    assert_eq!(
+        optimized(source, Pass::DeadStores),
+        "set '1' 0\nadd 0 '1' 1\nset '8' 3\nset '5' 2\nset &2 2\ncall F\nset '7' 4\nret\n\nF:\nset '6' 4\nret\n"
+    );
+}
+
+#[test]
+fn optimize_flag() {
+    let plain = yaul(example("sort")).output().unwrap();
+    let optimized = yaul(example("sort"))
+        .args(["-O", "--skip-pass", "dead-stores"])
+        .output()
+        .unwrap();
+
+    assert!(optimized.status.success());
+    assert_eq!(
+        program_output(&optimized.stdout),
+        program_output(&plain.stdout)
+    );
+
+    let output = yaul(example("sort"))
+        .args(["--skip-pass", "dead-stores"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
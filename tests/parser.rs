@@ -0,0 +1,68 @@
+mod common;
+
+use common::{program, yaul};
+use yaul::{BufferIo, Program, Runner};
+
+#[test]
+fn unresolved_label_points_at_label() {
+    let output = yaul(program("unresolved_label")).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("Unresolved label: NOWHERE"));
+    assert!(stderr.contains("unresolved_label:3:8"));
+    assert!(stderr.contains("3 | \tjmp   NOWHERE // tab indented\n  | \t      ^^^^^^^\n"));
+}
+
+#[test]
+fn missing_operand_is_reported() {
+    let output = yaul(program("missing_operand")).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("ADD expects 3 operands, found 2"));
+    assert!(stderr.contains("missing_operand:2:1"));
+}
+
+#[test]
+fn literal_destination_is_reported() {
+    let output = yaul(program("literal_destination")).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("Destination cannot be a value"));
+    assert!(stderr.contains("literal_destination:1:9"));
+}
+
+#[test]
+fn all_diagnostics_are_reported_in_line_order() {
+    let output = yaul(program("multiple_errors")).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+
+    let reported = [
+        "error: Unresolved label: NOWHERE",
+        "error: Unknown instruction: FOO",
+        "warning: Label UNUSED is never used",
+        "error: ADD expects 3 operands, found 2",
+        "Failed to parse instructions: 3 error(s)",
+    ]
+    .map(|message| stderr.find(message).expect(message));
+    assert!(reported.is_sorted());
+}
+
+#[test]
+fn blank_and_comment_lines_are_skipped() {
+    let program = Program::from_source("set '1' 0\n   \n\t// comment\n\u{3000}\nout 0").unwrap();
+    let mut runner = Runner::builder().io(BufferIo::new("")).build();
+    runner.run(&program).unwrap();
+    assert_eq!(runner.io().output_string(), "1\n");
+}
+
+#[test]
+fn short_lines_are_parsed() {
+    let error = Program::from_source("x").unwrap_err();
+    assert!(error.to_string().contains("Unknown instruction: X"));
+    assert!(Program::from_source("\u{3000}/").is_err());
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::{example, yaul};
+use yaul::{BufferIo, FunctionProfile, Profiler, Program, Runner};
+
+fn profile(program: &Program) -> Profiler<'_> {
+    let mut runner = Runner::builder()
+        .registers(128)
+        .io(BufferIo::new(""))
+        .observer(Profiler::new(program))
+        .build();
+    runner.run(program).unwrap();
+    runner.into_observer()
+}
+
+#[test]
+fn counts_instructions_and_calls() {
+    let program = Program::from_file(example("call")).unwrap();
+    let profiler = profile(&program);
+
+    assert_eq!(profiler.total(), 13);
+    assert_eq!(profiler.counts()[9], 2);
+    assert_eq!(
+        profiler.functions(),
+        [
+            FunctionProfile {
+                name: "<main>".to_string(),
+                calls: 1,
+                inclusive: 13,
+                exclusive: 9,
+            },
+            FunctionProfile {
+                name: "SQUARE".to_string(),
+                calls: 2,
+                inclusive: 4,
+                exclusive: 4,
+            },
+        ]
+    );
+    assert_eq!(
+        profiler.blocks(),
+        [("<main>".to_string(), 9), ("SQUARE".to_string(), 4)]
+    );
+}
+
+#[test]
+fn nested_and_recursive_calls() {
+    let source = "\
+set '2' 0
+call OUTER
+ret
+
+OUTER:
+call INNER
+ret
+
+INNER:
+sub 0 '1' 0
+jeq 0 '0' INNER_END
+call INNER
+INNER_END:
+ret
+";
+    let program = Program::from_source(source).unwrap();
+    let profiler = profile(&program);
+
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded).unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "<main> 3\n<main>;OUTER 2\n<main>;OUTER;INNER 4\n<main>;OUTER;INNER;INNER 3\n"
+    );
+
+    let inner = profiler
+        .functions()
+        .into_iter()
+        .find(|function| function.name == "INNER")
+        .unwrap();
+    assert_eq!((inner.calls, inner.inclusive, inner.exclusive), (2, 7, 7));
+}
+
+#[test]
+fn profile_flag() {
+    let folded = std::env::temp_dir().join(format!("yaul-profile-{}", std::process::id()));
+    let output = yaul(example("call"))
+        .arg("--profile")
+        .arg("--profile-folded")
+        .arg(&folded)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success());
+    assert!(stderr.contains("Profile: 13 instructions executed"));
+    assert!(stderr.contains("SQUARE"));
+    assert_eq!(
+        std::fs::read_to_string(&folded).unwrap(),
+        "<main> 9\n<main>;SQUARE 4\n"
+    );
+    std::fs::remove_file(folded).unwrap();
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use std::io::Write;
+use std::process::Stdio;
+
+use common::{example, program_output, yaul};
+use yaul::{BufferIo, Event, Program, Recording, Runner, RuntimeErrorKind, SyscallError};
+
+const SOURCE: &str = "\
+in 0
+cin 1 '3'
+time 4
+out 0
+";
+
+#[test]
+fn replays_recorded_values() {
+    let program = Program::from_source(SOURCE).unwrap();
+
+    let mut recorder = Runner::builder()
+        .registers(8)
+        .io(BufferIo::new("12\nab"))
+        .record()
+        .build();
+    recorder.run(&program).unwrap();
+
+    let recording = recorder.recording().unwrap().clone();
+    assert_eq!(recording.events.len(), 3);
+    assert_eq!(recording.events[0], Event::Input("12\n".to_string()));
+    assert_eq!(
+        recording.events[1],
+        Event::CharInput {
+            size: 3,
+            bytes: b"ab".to_vec()
+        }
+    );
+
+    let mut replayer = Runner::builder()
+        .registers(8)
+        .io(BufferIo::new(""))
+        .replay(recording)
+        .build();
+    replayer.run(&program).unwrap();
+
+    assert_eq!(replayer.registers(), recorder.registers());
+    assert_eq!(replayer.io().output_string(), "12\n");
+}
+
+#[test]
+fn detects_divergence() {
+    let program = Program::from_source(SOURCE).unwrap();
+    let recording = Recording {
+        events: vec![Event::Input("1\n".to_string()), Event::Time(5)],
+    };
+
+    let mut runner = Runner::builder()
+        .registers(8)
+        .io(BufferIo::new(""))
+        .replay(recording)
+        .build();
+    let error = runner.run(&program).unwrap_err();
+
+    assert_eq!(error.pc, 1);
+    assert!(matches!(
+        error.kind,
+        RuntimeErrorKind::ReplayDivergence { event: 1, .. }
+    ));
+    assert_eq!(
+        error.kind.to_string(),
+        "Replay diverged at event 1: the program wants cin 3 but the recording has time 5"
+    );
+}
+
+#[test]
+fn recording_round_trips_as_text() {
+    let recording = Recording {
+        events: vec![
+            Event::Input("4\r\n".to_string()),
+            Event::CharInput {
+                size: 4,
+                bytes: vec![b'"', b'\\', 0xff],
+            },
+            Event::Time(-3),
+            Event::Syscall {
+                number: 39,
+                result: Ok(1234),
+            },
+            Event::Syscall {
+                number: 1,
+                result: Err(SyscallError::Errno(9)),
+            },
+        ],
+    };
+
+    let text = recording.to_string();
+    assert_eq!(
+        text,
+        "yaul-replay 1\nin \"4\\r\\n\"\ncin 4 \"\\\"\\\\\\xff\"\ntime -3\nsys 39 1234\nsys 1 errno 9\n"
+    );
+    assert_eq!(text.parse::<Recording>(), Ok(recording));
+
+    assert!("yaul-replay 2\n".parse::<Recording>().is_err());
+    assert_eq!(
+        "yaul-replay 1\ntime\n".parse::<Recording>(),
+        Err("Line 2: Invalid number: ".to_string())
+    );
+}
+
+#[test]
+fn record_and_replay_flags() {
+    let path = std::env::temp_dir().join(format!("yaul-replay-{}", std::process::id()));
+
+    let mut child = yaul(example("count"))
+        .arg("--record")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"3\n").unwrap();
+    let recorded = child.wait_with_output().unwrap();
+    assert!(recorded.status.success());
+
+    let replayed = yaul(example("count"))
+        .arg("--replay")
+        .arg(&path)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(replayed.status.success());
+    assert_eq!(
+        program_output(&replayed.stdout),
+        program_output(&recorded.stdout)
+    );
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::{example, program, program_output, yaul};
+
+#[test]
+fn division_by_zero_is_reported() {
+    let output = yaul(program("division_by_zero")).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("Division by zero"));
+    assert!(stderr.contains("Instruction 3:"));
+    assert!(stderr.contains("Stack: [2]"));
+    assert!(stderr.contains("division_by_zero:6:1"));
+    assert!(stderr.contains("6 | div 0 '0' 1\n  | ^^^^^^^^^^^\n"));
+}
+
+#[test]
+fn register_out_of_bounds_is_reported() {
+    let output = yaul(program("register_out_of_bounds"))
+        .args(["--registers", "16"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("Register out of bounds: 100000"));
+}
+
+#[test]
+fn fault_is_reported() {
+    let output = yaul(example("fault")).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(program_output(&output.stdout).is_empty());
+    assert!(stderr.contains("Program fault: Custom fault message"));
+}
+
+#[test]
+fn operand_modes_agree() {
+    // Every arithmetic and jump instruction with each mix of register and value
+    // operands, next to the same instruction reading through references, which
+    // the runner doesn't specialize.
+    let source = "\
+set '17' 0
+set '5' 1
+set '0' 10
+set '1' 11
+add 0 1 2
+add &10 &11 3
+sub 0 '5' 4
+sub &10 '5' 5
+mul '3' 1 6
+mul '3' &11 7
+div 0 1 8
+div &10 &11 9
+mod '17' 1 12
+mod '17' &11 13
+out 2
+out 3
+out 4
+out 5
+out 6
+out 7
+out 8
+out 9
+out 12
+out 13
+jgt 0 '16' GREATER
+fault \"jgt\"
+GREATER:
+jeq '5' &11 EQUAL
+fault \"jeq\"
+EQUAL:
+jlt 1 0 LESS
+fault \"jlt\"
+LESS:
+jlt 0 1 WRONG
+ret
+WRONG:
+fault \"fell through\"
+";
+    let program = yaul::Program::from_source(source).unwrap();
+    let mut runner = yaul::Runner::builder()
+        .registers(16)
+        .io(yaul::BufferIo::new(""))
+        .build();
+
+    assert_eq!(runner.run(&program).unwrap(), yaul::ExitStatus::Returned);
+    assert_eq!(
+        runner.io().output_string(),
+        "22\n22\n12\n12\n15\n15\n3\n3\n2\n2\n"
+    );
+
+    let program = yaul::Program::from_source("set '0' 1\ndiv '4' 1 2").unwrap();
+    let error = runner.run(&program).unwrap_err();
+    assert!(matches!(error.kind, yaul::RuntimeErrorKind::DivisionByZero));
+    assert_eq!(error.pc, 1);
+}
+
+#[test]
+fn char_input_stops_at_the_last_register() {
+    let program = yaul::Program::from_source(
+        "cin 2 '1000000000000'\ncin 0 '2'\ncout 2\ncout 3\ncout 0\ncout 1",
+    )
+    .unwrap();
+    let mut runner = yaul::Runner::builder()
+        .registers(4)
+        .io(yaul::BufferIo::new("abcdef"))
+        .build();
+    runner.run(&program).unwrap();
+    assert_eq!(runner.io().output_string(), "abcd");
+
+    let program = yaul::Program::from_source("cin 10 '1'").unwrap();
+    let error = runner.run(&program).unwrap_err();
+    assert!(matches!(
+        error.kind,
+        yaul::RuntimeErrorKind::RegisterOutOfBounds(10)
+    ));
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::example;
+use yaul::{BufferIo, ExitStatus, Program, Runner, Snapshot, SnapshotError};
+
+fn runner() -> Runner<BufferIo> {
+    Runner::builder()
+        .registers(128)
+        .io(BufferIo::new(""))
+        .build()
+}
+
+#[test]
+fn resumes_from_snapshot() {
+    let program = Program::from_file(example("call")).unwrap();
+
+    let mut first = runner();
+    first.set_fuel(Some(5));
+    assert_eq!(first.run(&program).unwrap(), ExitStatus::OutOfFuel);
+    // Suspended inside the first call to SQUARE.
+    assert_eq!(first.stack(), &[4]);
+
+    let bytes = first.snapshot().to_bytes();
+    let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+    assert_eq!(snapshot, first.snapshot());
+
+    let mut second = runner();
+    second.restore(&snapshot).unwrap();
+    assert_eq!(second.pc(), 10);
+    assert_eq!(second.fuel(), Some(0));
+
+    second.set_fuel(None);
+    assert_eq!(second.resume(&program).unwrap(), ExitStatus::Returned);
+    assert_eq!(second.io().output_string(), "9\n25\n");
+}
+
+#[test]
+fn rejects_invalid_snapshots() {
+    let bytes = runner().snapshot().to_bytes();
+
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[0] = b'X';
+    assert!(matches!(
+        Snapshot::from_bytes(&wrong_magic),
+        Err(SnapshotError::InvalidMagic)
+    ));
+
+    let mut wrong_version = bytes.clone();
+    wrong_version[8] = 99;
+    assert!(matches!(
+        Snapshot::from_bytes(&wrong_version),
+        Err(SnapshotError::UnsupportedVersion(99))
+    ));
+
+    let error = Snapshot::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+    assert_eq!(error.to_string(), "Snapshot is truncated");
+
+    let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+    let mut smaller = Runner::new(4);
+    assert!(matches!(
+        smaller.restore(&snapshot),
+        Err(SnapshotError::RegisterCount {
+            expected: 4,
+            found: 128
+        })
+    ));
+}
@@ -0,0 +1,69 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+mod common;
+
+use common::{program, program_output, yaul};
+use std::io::Write;
+use std::process::Stdio;
+
+#[test]
+fn getpid() {
+    let child = yaul(program("syscall_linux_getpid"))
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(program_output(&output.stdout), vec![pid.to_string()]);
+}
+
+#[test]
+fn write() {
+    let output = yaul(program("syscall_linux_write")).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(program_output(&output.stdout), vec!["0"]);
+}
+
+#[test]
+fn exit() {
+    let output = yaul(program("syscall_linux_exit")).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(7));
+    assert!(program_output(&output.stdout).is_empty());
+}
+
+#[test]
+fn record_exit() {
+    let path = std::env::temp_dir().join(format!("yaul-record-exit-{}", std::process::id()));
+
+    let mut child = yaul(program("syscall_linux_record_exit"))
+        .arg("--record")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"5\n").unwrap();
+    let pid = child.id();
+    assert_eq!(child.wait().unwrap().code(), Some(7));
+
+    let recording = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        recording,
+        format!("yaul-replay 1\nin \"5\\n\"\nsys 39 {}\n", pid)
+    );
+
+    let replayed = yaul(program("syscall_linux_record_exit"))
+        .arg("--replay")
+        .arg(&path)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(replayed.status.code(), Some(7));
+
+    std::fs::remove_file(path).unwrap();
+}
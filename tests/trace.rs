@@ -0,0 +1,166 @@
+mod common;
+
+use common::{example, program_output, yaul};
+use yaul::{BufferIo, PcRange, Program, Runner, TraceFilter, Tracer};
+
+fn trace(source: &str, filter: TraceFilter) -> Vec<String> {
+    let program = Program::from_source(source).unwrap();
+
+    let mut runner = Runner::builder()
+        .registers(8)
+        .io(BufferIo::default())
+        .observer(Tracer::new(Vec::new(), filter))
+        .build();
+    let _ = runner.run(&program);
+
+    String::from_utf8(runner.observer().writer().clone())
+        .unwrap()
+        .lines()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[test]
+fn traces_operands_writes_and_depth() {
+    let lines = trace(
+        "set '2' 1\nset '5' 2\nadd 1 &1 &1\nout 2",
+        TraceFilter::default(),
+    );
+
+    assert_eq!(lines.len(), 4);
+    assert_eq!(
+        lines[2],
+        r#"{"step":2,"pc":2,"op":"ADD","operands":[2,5,{"reg":2}],"writes":[{"reg":2,"value":7}],"depth":0,"next":3}"#
+    );
+}
+
+#[test]
+fn failed_instructions_are_traced() {
+    let lines = trace("div '1' '0' 0", TraceFilter::default());
+
+    assert_eq!(
+        lines,
+        [
+            r#"{"step":0,"pc":0,"op":"DIV","operands":[1,0,{"reg":0}],"writes":[],"depth":0,"next":"failed"}"#
+        ]
+    );
+}
+
+#[test]
+fn filters_by_pc_range_and_opcode() {
+    let source = "set '1' 0\nLOOP:\nadd 0 0 0\njlt 0 '8' LOOP\nout 0";
+
+    let by_pc = trace(
+        source,
+        TraceFilter {
+            pc_ranges: vec!["2..".parse().unwrap()],
+            ..TraceFilter::default()
+        },
+    );
+    assert_eq!(by_pc.len(), 4);
+    assert!(by_pc
+        .iter()
+        .all(|line| line.contains(r#""op":"JLT""#) || line.contains(r#""op":"OUT""#)));
+
+    let by_opcode = trace(
+        source,
+        TraceFilter {
+            opcodes: vec!["ADD".to_string()],
+            ..TraceFilter::default()
+        },
+    );
+    assert_eq!(by_opcode.len(), 3);
+}
+
+#[test]
+fn parses_pc_ranges() {
+    assert_eq!("3".parse(), Ok(PcRange { start: 3, end: 3 }));
+    assert_eq!("3..5".parse(), Ok(PcRange { start: 3, end: 4 }));
+    assert_eq!("3..=5".parse(), Ok(PcRange { start: 3, end: 5 }));
+    assert_eq!("..2".parse(), Ok(PcRange { start: 0, end: 1 }));
+    assert!("5..5".parse::<PcRange>().is_err());
+    assert!("x".parse::<PcRange>().is_err());
+}
+
+#[test]
+fn logs_flag_writes_trace_file() {
+    let path = std::env::temp_dir().join(format!("yaul-trace-{}.jsonl", std::process::id()));
+
+    let output = yaul(example("call"))
+        .arg("--logs")
+        .arg(&path)
+        .args(["--logs-op", "mul,out"])
+        .output()
+        .unwrap();
+    let trace = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(program_output(&output.stdout), ["9", "25"]);
+    assert_eq!(trace.lines().count(), 4);
+    assert!(trace
+        .lines()
+        .next()
+        .unwrap()
+        .contains(r#""op":"MUL","operands":[3,3,{"reg":0}]"#));
+    assert!(trace
+        .lines()
+        .all(|line| line.contains(r#""depth":1"#) || line.contains(r#""op":"OUT""#)));
+}
+
+#[test]
+fn traces_host_function_writes() {
+    let program = Program::from_source("call host:answer").unwrap();
+
+    let mut runner = Runner::builder()
+        .registers(4)
+        .io(BufferIo::default())
+        .host_function("answer", |registers| {
+            registers[3] = 42;
+            Ok(())
+        })
+        .observer(Tracer::new(Vec::new(), TraceFilter::default()))
+        .build();
+    runner.run(&program).unwrap();
+
+    let trace = String::from_utf8(runner.observer().writer().clone()).unwrap();
+    assert!(trace.contains(r#""writes":[{"reg":3,"value":42}]"#));
+}
+
+#[test]
+fn logs_flag_reports_write_errors() {
+    let output = yaul(example("call"))
+        .args(["--logs", "/dev/full"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Failed to write /dev/full"));
+}
+
+#[test]
+fn logs_op_flag_rejects_unknown_opcodes() {
+    let output = yaul(example("call"))
+        .args(["--logs", "/dev/null", "--logs-op", "add,ad"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown opcode: ad"));
+}
+
+#[test]
+fn opcodes_are_listed() {
+    let program = Program::from_source(
+        "ret\nset '1' 0\nin 0\ncin 0 '1'\nout 0\ncout 0\nadd 0 0 0\nsub 0 0 0\nmul 0 0 0\n\
+         div 0 '1' 0\nmod 0 '1' 0\njmp END\njgt 0 0 END\njeq 0 0 END\njlt 0 0 END\ncall END\n\
+         time 0\nfault \"x\"\nsys 0 '0'\nEND:",
+    )
+    .unwrap();
+    let opcodes = program
+        .instructions()
+        .iter()
+        .map(|instruction| instruction.opcode())
+        .collect::<Vec<&str>>();
+    assert_eq!(opcodes, yaul::instructions::Instruction::OPCODES);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::{example, program_output, yaul};
+use yaul::{Access, BufferIo, ExitStatus, Program, Runner, WatchHit, Watchpoint};
+
+#[test]
+fn suspends_after_watched_write() {
+    let program = Program::from_file(example("call")).unwrap();
+    let mut runner = Runner::builder()
+        .registers(128)
+        .io(BufferIo::new(""))
+        .watch(Watchpoint::new(1, 1, Access::Write))
+        .build();
+
+    let status = runner.run(&program).unwrap();
+    assert_eq!(
+        status,
+        ExitStatus::Watchpoint(WatchHit {
+            pc: 1,
+            register: 1,
+            access: Access::Write,
+            old: 0,
+            value: 5,
+        })
+    );
+    assert_eq!(runner.pc(), 2);
+
+    // The write through `mul &100 &100 &100` in SQUARE.
+    let status = runner.resume(&program).unwrap();
+    assert!(matches!(
+        status,
+        ExitStatus::Watchpoint(WatchHit {
+            pc: 9,
+            old: 5,
+            value: 25,
+            ..
+        })
+    ));
+
+    assert_eq!(runner.resume(&program).unwrap(), ExitStatus::Returned);
+    assert_eq!(runner.io().output_string(), "9\n25\n");
+}
+
+#[test]
+fn watches_host_function_writes() {
+    let program = Program::from_source("call host:clobber\nout 2").unwrap();
+    let mut runner = Runner::builder()
+        .registers(4)
+        .io(BufferIo::new(""))
+        .host_function("clobber", |registers| {
+            registers[2] = 42;
+            Ok(())
+        })
+        .watch(Watchpoint::new(2, 2, Access::Write))
+        .build();
+
+    assert_eq!(
+        runner.run(&program).unwrap(),
+        ExitStatus::Watchpoint(WatchHit {
+            pc: 0,
+            register: 2,
+            access: Access::Write,
+            old: 0,
+            value: 42,
+        })
+    );
+}
+
+#[test]
+fn watches_reads() {
+    let program = Program::from_source("set '4' 0\nadd 0 0 1\nout 1").unwrap();
+    let mut runner = Runner::builder().registers(4).io(BufferIo::new("")).build();
+    runner.watch(Watchpoint::new(0, 0, Access::Read));
+
+    let status = runner.run(&program).unwrap();
+    assert!(matches!(
+        status,
+        ExitStatus::Watchpoint(WatchHit {
+            pc: 1,
+            access: Access::Read,
+            value: 4,
+            ..
+        })
+    ));
+
+    assert!(runner.unwatch(&Watchpoint::new(0, 0, Access::Read)));
+    assert_eq!(runner.resume(&program).unwrap(), ExitStatus::Completed);
+}
+
+#[test]
+fn parses_watchpoints() {
+    assert_eq!("5".parse(), Ok(Watchpoint::new(5, 5, Access::Write)));
+    assert_eq!(
+        "10..20:rw".parse(),
+        Ok(Watchpoint::new(10, 19, Access::ReadWrite))
+    );
+    assert_eq!("3..=4:r".parse(), Ok(Watchpoint::new(3, 4, Access::Read)));
+    assert_eq!(
+        "100..".parse(),
+        Ok(Watchpoint::new(100, usize::MAX, Access::Write))
+    );
+    assert_eq!("..2:r".parse(), Ok(Watchpoint::new(0, 1, Access::Read)));
+    assert_eq!(
+        Watchpoint::new(100, usize::MAX, Access::Write).to_string(),
+        "100..:w"
+    );
+    assert!("4..4".parse::<Watchpoint>().is_err());
+    assert!("4:x".parse::<Watchpoint>().is_err());
+}
+
+#[test]
+fn watch_flag_reports_source_line() {
+    let output = yaul(example("call")).arg("--watch=1").output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success());
+    assert!(stderr.contains("Watchpoint: r1 written: 0 -> 5 by instruction 1"));
+    assert!(stderr.contains("Watchpoint: r1 written: 5 -> 25 by instruction 9"));
+    assert!(stderr.contains("23 | mul &100 &100 &100"));
+    assert_eq!(program_output(&output.stdout), ["9", "25"]);
+}